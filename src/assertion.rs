@@ -0,0 +1,82 @@
+use std::fmt::Display;
+
+use serde::Deserialize;
+
+use crate::certificate::ConnectionInfo;
+use crate::request_error::RequestError;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Assertions {
+    cert_valid_for_days: Option<i64>,
+}
+
+pub struct AssertionResult {
+    pub passed: bool,
+    pub message: String,
+}
+
+impl Assertions {
+    // the certificates are only read for requests that check them
+    pub fn needs_connection(&self) -> bool {
+        self.cert_valid_for_days.is_some()
+    }
+
+    pub fn check(
+        &self,
+        connection: Option<&Result<ConnectionInfo, RequestError>>,
+    ) -> Vec<AssertionResult> {
+        let mut results = Vec::new();
+        if let Some(days) = self.cert_valid_for_days {
+            results.push(certificate_validity(days, connection));
+        }
+        results
+    }
+}
+
+fn certificate_validity(
+    days: i64,
+    connection: Option<&Result<ConnectionInfo, RequestError>>,
+) -> AssertionResult {
+    let failed = |message: String| AssertionResult {
+        passed: false,
+        message,
+    };
+    let chain = match connection {
+        None => return failed("no certificate to check, the request is not https".to_owned()),
+        Some(Err(e)) => return failed(format!("could not read the certificates: {e}")),
+        Some(Ok(connection)) if connection.chain.is_empty() => {
+            return failed("the server sent no certificate".to_owned())
+        }
+        Some(Ok(connection)) => &connection.chain,
+    };
+
+    // the first certificate of the chain to expire decides
+    let expiring = chain
+        .iter()
+        .min_by_key(|certificate| certificate.not_after)
+        .expect("the chain is not empty");
+    let days_left = expiring.days_left();
+    AssertionResult {
+        passed: days_left >= days,
+        message: format!(
+            "certificates valid for {days} days: {0} expires in {days_left} days",
+            expiring.subject
+        ),
+    }
+}
+
+impl Display for Assertions {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(days) = self.cert_valid_for_days {
+            writeln!(f, "certificates valid for at least {days} days")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for AssertionResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let outcome = if self.passed { "passed" } else { "FAILED" };
+        write!(f, "{outcome} {0}", self.message)
+    }
+}
@@ -0,0 +1,133 @@
+use std::fmt::{self, Debug, Display};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Deserialize;
+
+use crate::aws_sigv4::AwsSigV4Config;
+use crate::digest_auth::DigestConfig;
+use crate::oauth2::OAuth2Config;
+use crate::request_error::RequestError;
+use crate::request_message::RequestMessage;
+use crate::session::Session;
+
+#[derive(Clone, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Auth {
+    Basic {
+        username: String,
+        #[serde(default)]
+        password: String,
+    },
+    Bearer {
+        token: String,
+    },
+    ApiKey {
+        key: String,
+        value: String,
+        #[serde(default, rename = "in")]
+        location: ApiKeyLocation,
+    },
+    Digest(DigestConfig),
+    OAuth2(OAuth2Config),
+    #[serde(rename = "aws_sigv4")]
+    AwsSigV4(AwsSigV4Config),
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKeyLocation {
+    #[default]
+    Header,
+    Query,
+}
+
+impl Auth {
+    pub async fn apply(
+        &self,
+        request_message: &mut RequestMessage,
+        session: &Session,
+    ) -> Result<(), RequestError> {
+        match self {
+            Auth::Basic { username, password } => {
+                let credentials = STANDARD.encode(format!("{username}:{password}"));
+                request_message.set_header("Authorization", &format!("Basic {credentials}"));
+            }
+            Auth::Bearer { token } => {
+                request_message.set_header("Authorization", &format!("Bearer {token}"));
+            }
+            Auth::ApiKey {
+                key,
+                value,
+                location: ApiKeyLocation::Header,
+            } => request_message.set_header(key, value),
+            Auth::ApiKey {
+                key,
+                value,
+                location: ApiKeyLocation::Query,
+            } => request_message
+                .auth_query
+                .push((key.to_owned(), value.to_owned())),
+            // digest needs the server challenge, it is answered by the http client
+            Auth::Digest(_) => {}
+            Auth::OAuth2(config) => {
+                let token = config.access_token(request_message, session).await?;
+                request_message.set_header("Authorization", &format!("Bearer {token}"));
+            }
+            // the signature covers the time, url and cookies of each attempt, see sign
+            Auth::AwsSigV4(_) => {}
+        }
+        Ok(())
+    }
+
+    pub async fn sign(&self, request_message: &mut RequestMessage) -> Result<(), RequestError> {
+        if let Auth::AwsSigV4(config) = self {
+            config.sign(request_message).await?;
+        }
+        Ok(())
+    }
+}
+
+// the secrets are masked, a debug print of a request must not leak them
+impl Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Auth::Basic { username, .. } => f
+                .debug_struct("Basic")
+                .field("username", username)
+                .field("password", &"***")
+                .finish(),
+            Auth::Bearer { .. } => f.debug_struct("Bearer").field("token", &"***").finish(),
+            Auth::ApiKey { key, location, .. } => f
+                .debug_struct("ApiKey")
+                .field("key", key)
+                .field("value", &"***")
+                .field("location", location)
+                .finish(),
+            Auth::Digest(config) => f.debug_tuple("Digest").field(config).finish(),
+            Auth::OAuth2(config) => f.debug_tuple("OAuth2").field(config).finish(),
+            Auth::AwsSigV4(config) => f.debug_tuple("AwsSigV4").field(config).finish(),
+        }
+    }
+}
+
+impl Display for Auth {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Auth::Basic { username, .. } => write!(f, "basic, username {username}, password ***"),
+            Auth::Bearer { .. } => write!(f, "bearer ***"),
+            Auth::ApiKey {
+                key,
+                location: ApiKeyLocation::Header,
+                ..
+            } => write!(f, "apikey, header {key}: ***"),
+            Auth::ApiKey {
+                key,
+                location: ApiKeyLocation::Query,
+                ..
+            } => write!(f, "apikey, query {key}=***"),
+            Auth::Digest(config) => write!(f, "{config}"),
+            Auth::OAuth2(config) => write!(f, "{config}"),
+            Auth::AwsSigV4(config) => write!(f, "{config}"),
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    env,
+    fmt::Display,
+    fs,
+    path::PathBuf,
+};
+
+use async_std::io::{Read, ReadExt};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::request_body::RequestBody;
+use crate::request_error::RequestError;
+use crate::request_message::RequestMessage;
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+const UNSIGNED_HEADERS: [&str; 8] = [
+    "authorization",
+    "connection",
+    "keep-alive",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AwsSigV4Config {
+    region: String,
+    service: String,
+    profile: Option<String>,
+    #[serde(default)]
+    unsigned_payload: bool,
+}
+
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl AwsSigV4Config {
+    pub async fn sign(&self, request_message: &mut RequestMessage) -> Result<(), RequestError> {
+        let credentials = self.credentials()?;
+        let mut url = Url::parse(&request_message.url).map_err(|e| RequestError::InvalidUrl {
+            url: request_message.url.clone(),
+            message: e.to_string(),
+        })?;
+        // the query, and the path for s3, are sent exactly as they are signed
+        let canonical_uri = self.canonical_uri(&url);
+        let canonical_query = canonical_query(&url);
+        if self.service == "s3" {
+            url.set_path(&canonical_uri);
+        }
+        url.set_query(
+            Some(&canonical_query)
+                .filter(|q| !q.is_empty())
+                .map(|q| q.as_str()),
+        );
+        request_message.url = url.to_string();
+
+        let amz_date = Utc::now().format("%Y%m%dT%H%M%SZ").to_string();
+        let payload_hash = if self.unsigned_payload {
+            UNSIGNED_PAYLOAD.to_owned()
+        } else {
+            payload_hash(&request_message.body).await?
+        };
+
+        request_message.set_header("x-amz-date", &amz_date);
+        request_message.set_header("x-amz-content-sha256", &payload_hash);
+        if let Some(session_token) = &credentials.session_token {
+            request_message.set_header("x-amz-security-token", session_token);
+        }
+
+        let authorization = self.authorization(
+            &credentials,
+            &request_message.method.to_string(),
+            &url,
+            &request_message.headers,
+            &amz_date,
+            &payload_hash,
+        );
+        request_message.set_header("Authorization", &authorization);
+        Ok(())
+    }
+
+    // the url is signed as it is sent, its query already in canonical order
+    fn authorization(
+        &self,
+        credentials: &Credentials,
+        method: &str,
+        url: &Url,
+        headers: &HashMap<String, String>,
+        amz_date: &str,
+        payload_hash: &str,
+    ) -> String {
+        let date = &amz_date[..8];
+        let mut canonical_headers = BTreeMap::new();
+        let host = match url.port() {
+            Some(port) => format!("{0}:{port}", url.host_str().unwrap_or_default()),
+            None => url.host_str().unwrap_or_default().to_owned(),
+        };
+        canonical_headers.insert("host".to_owned(), host);
+        for (name, value) in headers.iter() {
+            let name = name.to_lowercase();
+            // an earlier signature and hop-by-hop headers do not reach the service as sent
+            if UNSIGNED_HEADERS.contains(&name.as_str()) {
+                continue;
+            }
+            let value = value.split_whitespace().collect::<Vec<&str>>().join(" ");
+            canonical_headers.insert(name, value);
+        }
+        let signed_headers = canonical_headers
+            .keys()
+            .cloned()
+            .collect::<Vec<String>>()
+            .join(";");
+
+        let canonical_request = format!(
+            "{0}\n{1}\n{2}\n{3}\n{4}\n{5}",
+            method,
+            self.canonical_uri(url),
+            canonical_query(url),
+            canonical_headers
+                .iter()
+                .map(|(name, value)| format!("{name}:{value}\n"))
+                .collect::<String>(),
+            signed_headers,
+            payload_hash
+        );
+
+        let scope = format!("{date}/{0}/{1}/aws4_request", self.region, self.service);
+        let string_to_sign = format!(
+            "{ALGORITHM}\n{amz_date}\n{scope}\n{0}",
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let signing_key = [self.region.as_str(), self.service.as_str(), "aws4_request"]
+            .iter()
+            .fold(
+                hmac_sha256(
+                    format!("AWS4{0}", credentials.secret_access_key).as_bytes(),
+                    date.as_bytes(),
+                ),
+                |key, part| hmac_sha256(&key, part.as_bytes()),
+            );
+        let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+        format!(
+            "{ALGORITHM} Credential={0}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            credentials.access_key_id
+        )
+    }
+
+    fn canonical_uri(&self, url: &Url) -> String {
+        let segments = url.path().split('/').map(|segment| {
+            // s3 signs the path encoded once, every other service encodes the sent path again
+            if self.service == "s3" {
+                aws_encode(&percent_encoding::percent_decode_str(segment).decode_utf8_lossy())
+            } else {
+                aws_encode(segment)
+            }
+        });
+        let path = segments.collect::<Vec<String>>().join("/");
+        if path.is_empty() {
+            "/".to_owned()
+        } else {
+            path
+        }
+    }
+
+    fn credentials(&self) -> Result<Credentials, RequestError> {
+        if self.profile.is_none() {
+            if let (Ok(access_key_id), Ok(secret_access_key)) = (
+                env::var("AWS_ACCESS_KEY_ID"),
+                env::var("AWS_SECRET_ACCESS_KEY"),
+            ) {
+                return Ok(Credentials {
+                    access_key_id,
+                    secret_access_key,
+                    session_token: env::var("AWS_SESSION_TOKEN").ok(),
+                });
+            }
+        }
+
+        let profile = self
+            .profile
+            .clone()
+            .or(env::var("AWS_PROFILE").ok())
+            .unwrap_or("default".to_owned());
+        let path = env::var("AWS_SHARED_CREDENTIALS_FILE")
+            .map(PathBuf::from)
+            .or(env::var("HOME").map(|home| PathBuf::from(home).join(".aws").join("credentials")))
+            .map_err(|_| RequestError::AuthError {
+                message: "could not find the aws credentials file".to_owned(),
+            })?;
+        let content = fs::read_to_string(&path).map_err(|e| RequestError::AuthError {
+            message: format!("could not read {0}: {e}", path.display()),
+        })?;
+
+        let values = profile_values(&content, &profile);
+        match (
+            values.get("aws_access_key_id"),
+            values.get("aws_secret_access_key"),
+        ) {
+            (Some(access_key_id), Some(secret_access_key)) => Ok(Credentials {
+                access_key_id: access_key_id.clone(),
+                secret_access_key: secret_access_key.clone(),
+                session_token: values.get("aws_session_token").cloned(),
+            }),
+            _ => Err(RequestError::AuthError {
+                message: format!(
+                    "profile {profile} in {0} has no aws_access_key_id or aws_secret_access_key",
+                    path.display()
+                ),
+            }),
+        }
+    }
+}
+
+fn profile_values(content: &str, profile: &str) -> BTreeMap<String, String> {
+    let mut values = BTreeMap::new();
+    let mut in_profile = false;
+    for line in content.lines().map(str::trim) {
+        if line.starts_with('#') || line.starts_with(';') || line.is_empty() {
+            continue;
+        }
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let section = section.trim();
+            in_profile = section == profile || section.strip_prefix("profile ") == Some(profile);
+            continue;
+        }
+        if let (true, Some((key, value))) = (in_profile, line.split_once('=')) {
+            values.insert(key.trim().to_owned(), value.trim().to_owned());
+        }
+    }
+    values
+}
+
+fn canonical_query(url: &Url) -> String {
+    let mut pairs = url
+        .query_pairs()
+        .map(|(k, v)| (aws_encode(&k), aws_encode(&v)))
+        .collect::<Vec<(String, String)>>();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(k, v)| format!("{k}={v}"))
+        .collect::<Vec<String>>()
+        .join("&")
+}
+
+fn aws_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+pub async fn payload_hash(body: &RequestBody) -> Result<String, RequestError> {
+    let path = match body {
+        RequestBody::Text(text) => return Ok(hex::encode(Sha256::digest(text.as_bytes()))),
+        RequestBody::Bytes(bytes) => return Ok(hex::encode(Sha256::digest(bytes))),
+        RequestBody::File(path) => path.as_path(),
+        RequestBody::CompressedFile(file) => file.path(),
+    };
+    let read_error = |e: std::io::Error| RequestError::InvalidValue {
+        property_name: "body_file".to_owned(),
+        message: format!("could not read {0}: {e}", path.display()),
+    };
+    // the hash covers the bytes sent, a compressed file is compressed again for it
+    let mut reader: Box<dyn Read + Send + Unpin> = match body {
+        RequestBody::CompressedFile(file) => Box::new(file.open().await.map_err(read_error)?),
+        _ => Box::new(async_std::fs::File::open(path).await.map_err(read_error)?),
+    };
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = reader.read(&mut buffer).await.map_err(read_error)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+impl Display for AwsSigV4Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let credentials = match &self.profile {
+            Some(profile) => format!("profile {profile}"),
+            None => "environment".to_owned(),
+        };
+        write!(
+            f,
+            "aws_sigv4, region {0}, service {1}, credentials from {credentials}",
+            self.region, self.service
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // from the aws signature version 4 test suite
+    const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+    const AMZ_DATE: &str = "20150830T123600Z";
+
+    fn authorization(method: &str, url: &str, headers: &[(&str, &str)]) -> String {
+        let config = AwsSigV4Config {
+            region: "us-east-1".to_owned(),
+            service: "service".to_owned(),
+            profile: None,
+            unsigned_payload: false,
+        };
+        let credentials = Credentials {
+            access_key_id: "AKIDEXAMPLE".to_owned(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_owned(),
+            session_token: None,
+        };
+        let mut all_headers = HashMap::from([("X-Amz-Date".to_owned(), AMZ_DATE.to_owned())]);
+        for (name, value) in headers {
+            all_headers.insert(name.to_string(), value.to_string());
+        }
+        let url = Url::parse(url).unwrap();
+        config.authorization(
+            &credentials,
+            method,
+            &url,
+            &all_headers,
+            AMZ_DATE,
+            EMPTY_SHA256,
+        )
+    }
+
+    #[test]
+    fn signs_get_vanilla() {
+        assert_eq!(
+            authorization("GET", "https://example.amazonaws.com/", &[]),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+        );
+    }
+
+    #[test]
+    fn signs_post_vanilla() {
+        assert_eq!(
+            authorization("POST", "https://example.amazonaws.com/", &[]),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=5da7c1a2acd57cee7505fc6676e4e544621c30862966e37dddb68e92efbe5d6b"
+        );
+    }
+
+    #[test]
+    fn signs_query_in_canonical_order() {
+        let url = Url::parse("https://example.amazonaws.com/?Param2=value2&Param1=value1").unwrap();
+        assert_eq!(canonical_query(&url), "Param1=value1&Param2=value2");
+        assert_eq!(
+            authorization(
+                "GET",
+                "https://example.amazonaws.com/?Param1=value1&Param2=value2",
+                &[]
+            ),
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+             SignedHeaders=host;x-amz-date, \
+             Signature=b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500"
+        );
+    }
+
+    #[test]
+    fn leaves_out_previous_signature_and_hop_by_hop_headers() {
+        let headers = [
+            ("Authorization", "AWS4-HMAC-SHA256 Signature=stale"),
+            ("Connection", "keep-alive"),
+            ("TE", "trailers"),
+        ];
+        assert_eq!(
+            authorization("GET", "https://example.amazonaws.com/", &headers),
+            authorization("GET", "https://example.amazonaws.com/", &[])
+        );
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    fmt::Display,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use async_std::{
+    fs::File,
+    io::{ReadExt, WriteExt},
+};
+use encoding_rs::{Decoder, Encoding, UTF_8};
+use iced::futures::{channel::mpsc::Sender, future, SinkExt};
+use iced::subscription::{self, Subscription};
+use isahc::AsyncBody;
+
+use crate::compression::WireSize;
+
+// only the start of the body is kept for display, the rest is counted or saved
+const PREVIEW_LIMIT: usize = 256 * 1024;
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+pub struct BodyStream {
+    body: AsyncBody,
+    decoder: Decoder,
+    save_to: Option<PathBuf>,
+    wire: Option<WireSize>,
+}
+
+#[derive(Debug, Clone)]
+pub enum TransferUpdate {
+    Received {
+        bytes: u64,
+        text: String,
+        truncated: bool,
+    },
+    Finished(Option<String>),
+}
+
+pub struct Transfer {
+    pub running: bool,
+    received: u64,
+    expected: Option<u64>,
+    started_at: Instant,
+    finished_in: Option<Duration>,
+    save_to: Option<PathBuf>,
+    wire: Option<WireSize>,
+    truncated: bool,
+    error: Option<String>,
+    id: u64,
+    stream: Arc<Mutex<Option<BodyStream>>>,
+}
+
+impl BodyStream {
+    pub fn new(
+        body: AsyncBody,
+        content_type: Option<&str>,
+        save_to: Option<PathBuf>,
+        wire: Option<WireSize>,
+    ) -> Self {
+        let encoding = content_type
+            .and_then(charset)
+            .and_then(|charset| Encoding::for_label(charset.as_bytes()))
+            .unwrap_or(UTF_8);
+        BodyStream {
+            body,
+            decoder: encoding.new_decoder(),
+            save_to,
+            wire,
+        }
+    }
+
+    // the whole body at once, for answers that are parsed rather than shown
+    pub async fn read_to_string(mut self) -> Result<String, String> {
+        let mut bytes = Vec::new();
+        self.body
+            .read_to_end(&mut bytes)
+            .await
+            .map_err(|e| e.to_string())?;
+        let mut text = String::new();
+        self.decode(&bytes, &mut text, usize::MAX);
+        self.decode(&[], &mut text, usize::MAX);
+        Ok(text)
+    }
+
+    async fn read(mut self, output: &mut Sender<TransferUpdate>) -> Result<(), String> {
+        let mut file = match &self.save_to {
+            Some(path) => Some(
+                File::create(path)
+                    .await
+                    .map_err(|e| format!("could not create {0}: {e}", path.display()))?,
+            ),
+            None => None,
+        };
+        let mut buffer = vec![0u8; 64 * 1024];
+        let mut previewed = 0;
+        let mut bytes = 0;
+        let mut text = String::new();
+        let mut truncated = false;
+        let mut last_sent = Instant::now();
+
+        let result = loop {
+            let read = match self.body.read(&mut buffer).await {
+                Ok(0) => break Ok(()),
+                Ok(read) => read,
+                Err(e) => break Err(e.to_string()),
+            };
+            let chunk = &buffer[..read];
+            if let Some(file) = file.as_mut() {
+                if let Err(e) = file.write_all(chunk).await {
+                    break Err(format!("could not save the body: {e}"));
+                }
+            }
+            bytes += read as u64;
+            if previewed < PREVIEW_LIMIT {
+                previewed += self.decode(chunk, &mut text, PREVIEW_LIMIT - previewed);
+                truncated = previewed >= PREVIEW_LIMIT;
+            }
+            if last_sent.elapsed() >= PROGRESS_INTERVAL {
+                let update = TransferUpdate::Received {
+                    bytes,
+                    text: std::mem::take(&mut text),
+                    truncated,
+                };
+                if output.send(update).await.is_err() {
+                    break Ok(());
+                }
+                bytes = 0;
+                last_sent = Instant::now();
+            }
+        };
+
+        if previewed < PREVIEW_LIMIT {
+            self.decode(&[], &mut text, PREVIEW_LIMIT - previewed);
+        }
+        let _ = output
+            .send(TransferUpdate::Received {
+                bytes,
+                text,
+                truncated,
+            })
+            .await;
+        if let Some(file) = file.as_mut() {
+            file.flush()
+                .await
+                .map_err(|e| format!("could not save the body: {e}"))?;
+        }
+        result
+    }
+
+    // an empty chunk marks the end of the body, returns the length of the added text
+    fn decode(&mut self, chunk: &[u8], text: &mut String, limit: usize) -> usize {
+        let mut decoded = String::with_capacity(
+            self.decoder
+                .max_utf8_buffer_length(chunk.len())
+                .unwrap_or(chunk.len() * 3),
+        );
+        let _ = self
+            .decoder
+            .decode_to_string(chunk, &mut decoded, chunk.is_empty());
+        let mut end = decoded.len().min(limit);
+        while !decoded.is_char_boundary(end) {
+            end -= 1;
+        }
+        text.push_str(&decoded[..end]);
+        // a character cut at the limit still counts so the preview stops there
+        if end < decoded.len() {
+            limit
+        } else {
+            end
+        }
+    }
+}
+
+fn charset(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|parameter| {
+        parameter
+            .split_once('=')
+            .filter(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+            .map(|(_, value)| value.trim().trim_matches('"'))
+    })
+}
+
+impl Transfer {
+    pub fn new(stream: BodyStream, expected: Option<u64>) -> Self {
+        Transfer {
+            running: true,
+            received: 0,
+            expected,
+            started_at: Instant::now(),
+            finished_in: None,
+            save_to: stream.save_to.clone(),
+            wire: stream.wire.clone(),
+            truncated: false,
+            error: None,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            stream: Arc::new(Mutex::new(Some(stream))),
+        }
+    }
+
+    pub fn subscription(&self) -> Subscription<TransferUpdate> {
+        if !self.running {
+            return Subscription::none();
+        }
+        let stream = self.stream.clone();
+        subscription::channel(self.id, 100, move |mut output| async move {
+            let stream = stream.lock().ok().and_then(|mut s| s.take());
+            let result = match stream {
+                Some(stream) => stream.read(&mut output).await,
+                None => Ok(()),
+            };
+            let _ = output.send(TransferUpdate::Finished(result.err())).await;
+            future::pending().await
+        })
+    }
+
+    // the preview text is added to the given body
+    pub fn update(&mut self, update: TransferUpdate, body: &mut String) {
+        match update {
+            TransferUpdate::Received {
+                bytes,
+                text,
+                truncated,
+            } => {
+                self.received += bytes;
+                body.push_str(&text);
+                if truncated && !self.truncated {
+                    body.push_str(&format!(
+                        "\n\n[preview stopped after {0}]",
+                        size(PREVIEW_LIMIT as u64)
+                    ));
+                    self.truncated = true;
+                }
+            }
+            TransferUpdate::Finished(error) => self.finish(error),
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.finish(Some("stopped".to_owned()));
+    }
+
+    fn finish(&mut self, error: Option<String>) {
+        if self.running {
+            self.running = false;
+            self.finished_in = Some(self.started_at.elapsed());
+            self.error = error;
+        }
+    }
+}
+
+fn size(bytes: u64) -> String {
+    let units = ["KB", "MB", "GB"];
+    let mut value = bytes as f64;
+    let mut unit = "B";
+    for next in units {
+        if value < 1024.0 {
+            break;
+        }
+        value /= 1024.0;
+        unit = next;
+    }
+    match unit {
+        "B" => format!("{bytes} B"),
+        unit => format!("{value:.1} {unit}"),
+    }
+}
+
+impl Display for Transfer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let elapsed = self
+            .finished_in
+            .unwrap_or_else(|| self.started_at.elapsed());
+        write!(f, "{0}", size(self.received))?;
+        // the content length counts the encoded bytes
+        let wire = self.wire.as_ref().filter(|wire| wire.decoded());
+        if let Some(wire) = wire {
+            write!(f, " decoded, {0}", size(wire.bytes()))?;
+        }
+        if let Some(expected) = self.expected {
+            write!(f, " of {0}", size(expected))?;
+        }
+        if wire.is_some() {
+            write!(f, " on the wire")?;
+        }
+        let rate = self.received as f64 / elapsed.as_secs_f64().max(0.001);
+        write!(
+            f,
+            " in {0:.1}s, {1}/s",
+            elapsed.as_secs_f64(),
+            size(rate as u64)
+        )?;
+        let saved = self.running || self.error.is_none();
+        if let Some(path) = self.save_to.as_ref().filter(|_| saved) {
+            let verb = if self.running { "saving" } else { "saved" };
+            write!(f, ", {verb} to {0}", path.display())?;
+        }
+        // exact counts once the body is complete
+        match (&self.wire, self.running) {
+            (_, true) => {}
+            (Some(wire), false) if wire.decoded() => {
+                write!(f, ", {0} B decoded from {wire}", self.received)?
+            }
+            (Some(wire), false) => write!(f, ", {wire}, not decoded")?,
+            (None, false) => write!(f, ", {0} B", self.received)?,
+        }
+        match (&self.error, self.running) {
+            (_, true) => write!(f, ", receiving"),
+            (Some(error), false) => write!(f, ", {error}"),
+            (None, false) => write!(f, ", done"),
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    fmt::Display,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use async_std::task;
+use chrono::{DateTime, Utc};
+use openssl::{
+    asn1::{Asn1Time, Asn1TimeRef},
+    hash::MessageDigest,
+    ssl::{SslConnector, SslMethod, SslVerifyMode},
+    x509::{X509NameRef, X509Ref},
+};
+use url::Url;
+
+use crate::proxy::{self, Proxy};
+use crate::request_error::RequestError;
+use crate::tls::TlsConfig;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+// where a response came from, kept so its certificates can be read when asked for
+pub struct Peer {
+    url: Url,
+    address: Option<SocketAddr>,
+    tls: Option<TlsConfig>,
+    proxy: Option<Proxy>,
+    timeout: Option<Duration>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConnectionInfo {
+    pub tls_version: String,
+    pub cipher: String,
+    pub chain: Vec<CertificateInfo>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CertificateInfo {
+    pub subject: String,
+    pub issuer: String,
+    pub alternative_names: Vec<String>,
+    pub not_before: i64,
+    pub not_after: i64,
+    pub fingerprint: String,
+}
+
+impl Peer {
+    pub fn new(
+        url: Url,
+        address: Option<SocketAddr>,
+        tls: Option<TlsConfig>,
+        proxy: Option<Proxy>,
+        timeout: Option<Duration>,
+    ) -> Self {
+        Peer {
+            url,
+            address,
+            tls,
+            proxy,
+            timeout,
+        }
+    }
+
+    pub async fn inspect(self) -> Result<ConnectionInfo, RequestError> {
+        task::spawn_blocking(move || inspect(&self)).await
+    }
+}
+
+// the http client does not expose the peer certificates, so a separate handshake
+// with the same tls settings is made to read them
+fn inspect(peer: &Peer) -> Result<ConnectionInfo, RequestError> {
+    let url = &peer.url;
+    let host = url.host_str().unwrap_or_default();
+    let port = url.port_or_known_default().unwrap_or(443);
+    let failed = |message: String| RequestError::TlsError {
+        url: url.to_string(),
+        message,
+    };
+    let timeout = peer.timeout.unwrap_or(CONNECT_TIMEOUT);
+
+    let stream = match (&peer.proxy, &peer.address) {
+        (Some(proxy), _) => proxy.connect(host, port, timeout),
+        // the address the request went to, another backend may show other certificates
+        (None, Some(address)) => proxy::connect_to(address, timeout),
+        (None, None) => proxy::connect(host, port, timeout),
+    }
+    .map_err(|e| failed(e.to_string()))?;
+
+    let mut builder =
+        SslConnector::builder(SslMethod::tls_client()).map_err(|e| failed(e.to_string()))?;
+    // certificates are read even when they would not be trusted
+    builder.set_verify(SslVerifyMode::NONE);
+    if let Some(tls) = &peer.tls {
+        tls.configure_inspection(&mut builder)
+            .map_err(|e| failed(e.to_string()))?;
+    }
+    let stream = builder
+        .build()
+        .configure()
+        .map_err(|e| failed(e.to_string()))?
+        .verify_hostname(false)
+        .connect(host, stream)
+        .map_err(|e| failed(e.to_string()))?;
+
+    let ssl = stream.ssl();
+    let chain = ssl
+        .peer_cert_chain()
+        .map(|chain| chain.iter().map(certificate_info).collect())
+        .unwrap_or_default();
+    Ok(ConnectionInfo {
+        tls_version: ssl.version_str().to_owned(),
+        cipher: ssl
+            .current_cipher()
+            .map(|c| c.name().to_owned())
+            .unwrap_or_default(),
+        chain,
+    })
+}
+
+fn certificate_info(certificate: &X509Ref) -> CertificateInfo {
+    let alternative_names = certificate
+        .subject_alt_names()
+        .map(|names| {
+            names
+                .iter()
+                .filter_map(|name| {
+                    name.dnsname()
+                        .map(str::to_owned)
+                        .or(name.ipaddress().and_then(ip_address))
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    let fingerprint = certificate
+        .digest(MessageDigest::sha256())
+        .map(|digest| {
+            digest
+                .iter()
+                .map(|b| format!("{b:02X}"))
+                .collect::<Vec<String>>()
+                .join(":")
+        })
+        .unwrap_or_default();
+
+    CertificateInfo {
+        subject: name(certificate.subject_name()),
+        issuer: name(certificate.issuer_name()),
+        alternative_names,
+        not_before: timestamp(certificate.not_before()),
+        not_after: timestamp(certificate.not_after()),
+        fingerprint,
+    }
+}
+
+fn name(name: &X509NameRef) -> String {
+    name.entries()
+        .map(|entry| {
+            let key = entry.object().nid().short_name().unwrap_or("?");
+            let value = entry.data().to_string().unwrap_or_default();
+            format!("{key}={value}")
+        })
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+fn timestamp(time: &Asn1TimeRef) -> i64 {
+    Asn1Time::from_unix(0)
+        .and_then(|epoch| epoch.diff(time))
+        .map(|diff| i64::from(diff.days) * 86400 + i64::from(diff.secs))
+        .unwrap_or_default()
+}
+
+fn ip_address(bytes: &[u8]) -> Option<String> {
+    match bytes.len() {
+        4 => Some(IpAddr::from(Ipv4Addr::from(<[u8; 4]>::try_from(bytes).ok()?)).to_string()),
+        16 => Some(IpAddr::from(Ipv6Addr::from(<[u8; 16]>::try_from(bytes).ok()?)).to_string()),
+        _ => None,
+    }
+}
+
+impl CertificateInfo {
+    pub fn days_left(&self) -> i64 {
+        (self.not_after - Utc::now().timestamp()).div_euclid(86400)
+    }
+}
+
+fn date(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .map(|d| d.format("%Y-%m-%d %H:%M:%S UTC").to_string())
+        .unwrap_or_default()
+}
+
+impl Display for ConnectionInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{0} {1}", self.tls_version, self.cipher)?;
+        for (index, certificate) in self.chain.iter().enumerate() {
+            writeln!(f, "\ncertificate {index}")?;
+            write!(f, "{certificate}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for CertificateInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "subject {0}", self.subject)?;
+        writeln!(f, "issuer {0}", self.issuer)?;
+        if !self.alternative_names.is_empty() {
+            writeln!(
+                f,
+                "alternative names {0}",
+                self.alternative_names.join(", ")
+            )?;
+        }
+        writeln!(
+            f,
+            "valid from {0} until {1} ({2} days left)",
+            date(self.not_before),
+            date(self.not_after),
+            self.days_left()
+        )?;
+        writeln!(f, "sha256 {0}", self.fingerprint)
+    }
+}
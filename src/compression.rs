@@ -0,0 +1,247 @@
+use std::{
+    fmt::Display,
+    path::{Path, PathBuf},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    task::{Context, Poll},
+};
+
+use async_compression::futures::bufread::{
+    BrotliDecoder, BrotliEncoder, GzipDecoder, GzipEncoder, ZlibDecoder, ZlibEncoder, ZstdDecoder,
+    ZstdEncoder,
+};
+use async_std::fs::File;
+use async_std::io::{BufReader, Read, ReadExt};
+use isahc::AsyncBody;
+use serde::Deserialize;
+
+use crate::request_error::RequestError;
+
+// every encoding that can be decoded, offered unless the request picks its own
+const ENCODINGS: [&str; 4] = ["gzip", "deflate", "br", "zstd"];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CompressionConfig {
+    accept: Option<Vec<String>>,
+    #[serde(default = "default_decompress")]
+    decompress: bool,
+    request: Option<String>,
+}
+
+fn default_decompress() -> bool {
+    true
+}
+
+// the bytes read from the connection, shared with the transfer showing them
+#[derive(Clone)]
+pub struct WireSize {
+    bytes: Arc<AtomicU64>,
+    encoding: String,
+    decoded: bool,
+}
+
+pub struct CompressedBody {
+    pub encoding: String,
+    pub original: u64,
+    // a body file is compressed while it is sent, its size is known once it went out
+    pub compressed: Arc<AtomicU64>,
+}
+
+// a body file read through an encoder for every attempt instead of held in memory
+#[derive(Clone)]
+pub struct CompressedFile {
+    path: PathBuf,
+    encoding: String,
+    sent: Arc<AtomicU64>,
+}
+
+type Reader = Box<dyn Read + Send + Sync + Unpin>;
+
+struct Counted<R> {
+    inner: R,
+    bytes: Arc<AtomicU64>,
+}
+
+impl CompressionConfig {
+    pub fn validate(&self) -> Result<(), RequestError> {
+        match self.request.as_deref() {
+            Some(encoding) if !ENCODINGS.contains(&encoding) => Err(RequestError::InvalidValue {
+                property_name: "compression.request".to_owned(),
+                message: format!("unknown encoding {encoding}, use gzip, deflate, br or zstd"),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn request(&self) -> Option<&str> {
+        self.request.as_deref()
+    }
+}
+
+impl Display for CompressionConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.accept {
+            Some(accept) if accept.is_empty() => writeln!(f, "accept: nothing")?,
+            Some(accept) => writeln!(f, "accept: {0}", accept.join(", "))?,
+            None => writeln!(f, "accept: {0}", ENCODINGS.join(", "))?,
+        }
+        if !self.decompress {
+            writeln!(f, "responses are not decoded")?;
+        }
+        if let Some(request) = &self.request {
+            writeln!(f, "request body sent with {request}")?;
+        }
+        Ok(())
+    }
+}
+
+// an empty list sends no Accept-Encoding at all
+pub fn accept_encoding(config: Option<&CompressionConfig>) -> Option<String> {
+    match config.and_then(|c| c.accept.as_ref()) {
+        Some(accept) if accept.is_empty() => None,
+        Some(accept) => Some(accept.join(", ")),
+        None => Some(ENCODINGS.join(", ")),
+    }
+}
+
+// curl is told not to decode so the bytes it received can be counted,
+// the body is decoded here instead, undoing the encodings from the last one
+pub fn decode(
+    body: AsyncBody,
+    content_encoding: Option<&str>,
+    config: Option<&CompressionConfig>,
+) -> (AsyncBody, Option<WireSize>) {
+    let encodings: Vec<String> = content_encoding
+        .into_iter()
+        .flat_map(|value| value.split(','))
+        .map(|encoding| encoding.trim().to_ascii_lowercase())
+        .filter(|encoding| !encoding.is_empty() && encoding != "identity")
+        .collect();
+    if encodings.is_empty() {
+        return (body, None);
+    }
+    let known = encodings
+        .iter()
+        .all(|encoding| ENCODINGS.contains(&encoding.as_str()) || encoding == "x-gzip");
+    let decompress = config.is_none_or(|c| c.decompress);
+
+    let bytes = Arc::new(AtomicU64::new(0));
+    let mut reader: Reader = Box::new(Counted {
+        inner: body,
+        bytes: bytes.clone(),
+    });
+    if known && decompress {
+        for encoding in encodings.iter().rev() {
+            let buffered = BufReader::new(reader);
+            reader = match encoding.as_str() {
+                "gzip" | "x-gzip" => {
+                    let mut decoder = GzipDecoder::new(buffered);
+                    decoder.multiple_members(true);
+                    Box::new(decoder)
+                }
+                "deflate" => Box::new(ZlibDecoder::new(buffered)),
+                "br" => Box::new(BrotliDecoder::new(buffered)),
+                _ => Box::new(ZstdDecoder::new(buffered)),
+            };
+        }
+    }
+    let wire = WireSize {
+        bytes,
+        encoding: encodings.join(", "),
+        decoded: known && decompress,
+    };
+    (AsyncBody::from_reader(reader), Some(wire))
+}
+
+fn encoder(reader: BufReader<File>, encoding: &str) -> Reader {
+    match encoding {
+        "gzip" => Box::new(GzipEncoder::new(reader)),
+        "deflate" => Box::new(ZlibEncoder::new(reader)),
+        "br" => Box::new(BrotliEncoder::new(reader)),
+        _ => Box::new(ZstdEncoder::new(reader)),
+    }
+}
+
+pub async fn compress(bytes: &[u8], encoding: &str) -> std::io::Result<Vec<u8>> {
+    let mut compressed = Vec::new();
+    match encoding {
+        "gzip" => GzipEncoder::new(bytes).read_to_end(&mut compressed).await,
+        "deflate" => ZlibEncoder::new(bytes).read_to_end(&mut compressed).await,
+        "br" => BrotliEncoder::new(bytes).read_to_end(&mut compressed).await,
+        _ => ZstdEncoder::new(bytes).read_to_end(&mut compressed).await,
+    }?;
+    Ok(compressed)
+}
+
+impl CompressedFile {
+    pub fn new(path: PathBuf, encoding: &str) -> CompressedFile {
+        CompressedFile {
+            path,
+            encoding: encoding.to_owned(),
+            sent: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // counts the compressed bytes read from the last reader opened
+    pub fn sent(&self) -> Arc<AtomicU64> {
+        self.sent.clone()
+    }
+
+    pub async fn open(&self) -> std::io::Result<impl Read + Send + Sync + Unpin> {
+        let file = BufReader::new(File::open(&self.path).await?);
+        self.sent.store(0, Ordering::Relaxed);
+        Ok(Counted {
+            inner: encoder(file, &self.encoding),
+            bytes: self.sent.clone(),
+        })
+    }
+}
+
+impl WireSize {
+    pub fn bytes(&self) -> u64 {
+        self.bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn decoded(&self) -> bool {
+        self.decoded
+    }
+}
+
+impl Display for WireSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{0} B with {1}", self.bytes(), self.encoding)
+    }
+}
+
+impl Display for CompressedBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{0} B sent as {1} B with {2}",
+            self.original,
+            self.compressed.load(Ordering::Relaxed),
+            self.encoding
+        )
+    }
+}
+
+impl<R: Read + Unpin> Read for Counted<R> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(read)) = &poll {
+            self.bytes.fetch_add(*read as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+}
@@ -0,0 +1,332 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub domain: String,
+    pub path: String,
+    host_only: bool,
+    secure: bool,
+    http_only: bool,
+    expires: Option<i64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CookieJar {
+    cookies: Vec<Cookie>,
+}
+
+impl CookieJar {
+    pub fn cookies(&self) -> &[Cookie] {
+        &self.cookies
+    }
+
+    pub fn store(&mut self, url: &Url, set_cookie: &str) {
+        let Some(cookie) = Cookie::parse(url, set_cookie) else {
+            return;
+        };
+        self.cookies.retain(|c| {
+            !(c.name == cookie.name && c.domain == cookie.domain && c.path == cookie.path)
+        });
+        if !cookie.is_expired() {
+            self.cookies.push(cookie);
+        }
+    }
+
+    pub fn header_for(&mut self, url: &Url) -> Option<String> {
+        self.cookies.retain(|c| !c.is_expired());
+        let mut matching = self
+            .cookies
+            .iter()
+            .filter(|c| c.matches(url))
+            .collect::<Vec<&Cookie>>();
+        // cookies with longer paths are listed first
+        matching.sort_by_key(|c| std::cmp::Reverse(c.path.len()));
+
+        let header = matching
+            .iter()
+            .map(|c| format!("{0}={1}", c.name, c.value))
+            .collect::<Vec<String>>()
+            .join("; ");
+        Some(header).filter(|h| !h.is_empty())
+    }
+
+    pub fn set_value(&mut self, index: usize, value: String) {
+        if let Some(cookie) = self.cookies.get_mut(index) {
+            cookie.value = value;
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.cookies.len() {
+            self.cookies.remove(index);
+        }
+    }
+}
+
+impl Cookie {
+    fn parse(url: &Url, set_cookie: &str) -> Option<Cookie> {
+        let host = url.host_str()?.to_lowercase();
+        let parsed = cookie::Cookie::parse(set_cookie).ok()?;
+        if parsed.name().is_empty() {
+            return None;
+        }
+
+        let mut cookie = Cookie {
+            name: parsed.name().to_owned(),
+            value: parsed.value_trimmed().to_owned(),
+            domain: host.clone(),
+            path: default_path(url),
+            host_only: true,
+            secure: parsed.secure().unwrap_or_default(),
+            http_only: parsed.http_only().unwrap_or_default(),
+            // every cookie date format is read, an unreadable one leaves a session cookie
+            expires: parsed.expires_datetime().map(|d| d.unix_timestamp()),
+        };
+        if let Some(domain) = parsed.domain().filter(|d| !d.is_empty()) {
+            let domain = domain.to_lowercase();
+            if !domain_matches(&host, &domain) {
+                return None;
+            }
+            // a public suffix or an ip address only ever names the host itself
+            if is_public_suffix(&domain) || url.domain().is_none() {
+                if domain != host {
+                    return None;
+                }
+            } else {
+                cookie.domain = domain;
+                cookie.host_only = false;
+            }
+        }
+        if let Some(path) = parsed.path().filter(|p| p.starts_with('/')) {
+            cookie.path = path.to_owned();
+        }
+        if let Some(max_age) = parsed.max_age() {
+            cookie.expires = Some(Utc::now().timestamp() + max_age.whole_seconds());
+        }
+        Some(cookie)
+    }
+
+    fn is_expired(&self) -> bool {
+        self.expires
+            .is_some_and(|expires| expires <= Utc::now().timestamp())
+    }
+
+    fn matches(&self, url: &Url) -> bool {
+        let host = url.host_str().unwrap_or_default().to_lowercase();
+        let domain_ok = if self.host_only {
+            host == self.domain
+        } else {
+            domain_matches(&host, &self.domain)
+        };
+        let path = url.path();
+        let path_ok = path == self.path
+            || (path.starts_with(&self.path)
+                && (self.path.ends_with('/') || path[self.path.len()..].starts_with('/')));
+        let scheme_ok = !self.secure || url.scheme() == "https";
+
+        domain_ok && path_ok && scheme_ok
+    }
+}
+
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(&format!(".{domain}"))
+}
+
+// unknown top level names count as suffixes too
+fn is_public_suffix(domain: &str) -> bool {
+    psl::suffix_str(domain) == Some(domain)
+}
+
+fn default_path(url: &Url) -> String {
+    match url.path().rfind('/') {
+        Some(0) | None => "/".to_owned(),
+        Some(index) => url.path()[..index].to_owned(),
+    }
+}
+
+impl Display for Cookie {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{0} {1}", self.domain, self.path)?;
+        match self.expires.and_then(|e| DateTime::from_timestamp(e, 0)) {
+            Some(expires) => write!(f, " expires {0}", expires.format("%Y-%m-%d %H:%M:%S"))?,
+            None => write!(f, " session")?,
+        }
+        if self.secure {
+            write!(f, " secure")?;
+        }
+        if self.http_only {
+            write!(f, " httponly")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn url(text: &str) -> Url {
+        Url::parse(text).unwrap()
+    }
+
+    fn jar_with(set_by: &str, set_cookie: &str) -> CookieJar {
+        let mut jar = CookieJar::default();
+        jar.store(&url(set_by), set_cookie);
+        jar
+    }
+
+    #[test]
+    fn host_only_cookie_stays_on_its_host() {
+        let mut jar = jar_with("https://api.example.com/", "id=1");
+        assert_eq!(
+            jar.header_for(&url("https://api.example.com/")).as_deref(),
+            Some("id=1")
+        );
+        assert_eq!(jar.header_for(&url("https://www.api.example.com/")), None);
+    }
+
+    #[test]
+    fn domain_cookie_reaches_subdomains() {
+        let mut jar = jar_with("https://api.example.com/", "id=1; Domain=.Example.com");
+        assert_eq!(
+            jar.header_for(&url("https://www.example.com/")).as_deref(),
+            Some("id=1")
+        );
+        assert_eq!(jar.header_for(&url("https://notexample.com/")), None);
+    }
+
+    #[test]
+    fn refuses_domain_of_another_site() {
+        assert!(
+            jar_with("https://api.example.com/", "id=1; Domain=other.com")
+                .cookies()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn refuses_public_suffix_domains() {
+        assert!(
+            jar_with("https://shop.example.co.uk/", "id=1; Domain=co.uk")
+                .cookies()
+                .is_empty()
+        );
+        assert!(jar_with("https://api.example.com/", "id=1; Domain=com")
+            .cookies()
+            .is_empty());
+        // a suffix naming the host itself keeps the cookie for that host only
+        let mut jar = jar_with("https://localhost/", "id=1; Domain=localhost");
+        assert_eq!(
+            jar.header_for(&url("https://localhost/")).as_deref(),
+            Some("id=1")
+        );
+        assert!(jar.cookies()[0].host_only);
+    }
+
+    #[test]
+    fn refuses_suffixes_of_the_public_suffix_list() {
+        for (site, suffix) in [
+            ("https://shop.example.com.sg/", "com.sg"),
+            ("https://shop.example.co.id/", "co.id"),
+            ("https://bucket.s3.amazonaws.com/", "s3.amazonaws.com"),
+        ] {
+            let jar = jar_with(site, &format!("id=1; Domain={suffix}"));
+            assert!(jar.cookies().is_empty(), "{suffix} was accepted");
+        }
+        let mut jar = jar_with(
+            "https://shop.example.com.sg/",
+            "id=1; Domain=example.com.sg",
+        );
+        assert_eq!(
+            jar.header_for(&url("https://www.example.com.sg/"))
+                .as_deref(),
+            Some("id=1")
+        );
+    }
+
+    #[test]
+    fn reads_the_three_cookie_date_formats() {
+        // 784111777 is Sun, 06 Nov 1994 08:49:37 GMT
+        for expires in [
+            "Sun, 06 Nov 1994 08:49:37 GMT",
+            "Sunday, 06-Nov-94 08:49:37 GMT",
+            "Sun Nov  6 08:49:37 1994",
+            "Sun, 06-Nov-1994 08:49:37 GMT",
+        ] {
+            let cookie = Cookie::parse(
+                &url("https://example.com/"),
+                &format!("id=1; Expires={expires}"),
+            );
+            assert_eq!(cookie.unwrap().expires, Some(784111777), "{expires}");
+        }
+    }
+
+    #[test]
+    fn max_age_wins_over_expires() {
+        let cookie = Cookie::parse(
+            &url("https://example.com/"),
+            "id=1; Expires=Sun, 06 Nov 1994 08:49:37 GMT; Max-Age=60",
+        )
+        .unwrap();
+        assert!(!cookie.is_expired());
+    }
+
+    #[test]
+    fn refuses_domain_inside_an_ip_address() {
+        assert!(jar_with("http://10.0.0.1/", "id=1; Domain=0.0.1")
+            .cookies()
+            .is_empty());
+    }
+
+    #[test]
+    fn matches_paths_on_segment_boundaries() {
+        let mut jar = jar_with("https://example.com/", "id=1; Path=/docs");
+        assert_eq!(
+            jar.header_for(&url("https://example.com/docs")).as_deref(),
+            Some("id=1")
+        );
+        assert_eq!(
+            jar.header_for(&url("https://example.com/docs/a"))
+                .as_deref(),
+            Some("id=1")
+        );
+        assert_eq!(jar.header_for(&url("https://example.com/docsearch")), None);
+        assert_eq!(jar.header_for(&url("https://example.com/")), None);
+    }
+
+    #[test]
+    fn default_path_is_the_directory_of_the_request() {
+        let jar = jar_with("https://example.com/a/b/c", "id=1");
+        assert_eq!(jar.cookies()[0].path, "/a/b");
+    }
+
+    #[test]
+    fn lists_longer_paths_first() {
+        let mut jar = jar_with("https://example.com/", "a=1; Path=/");
+        jar.store(&url("https://example.com/"), "b=2; Path=/docs");
+        assert_eq!(
+            jar.header_for(&url("https://example.com/docs/x"))
+                .as_deref(),
+            Some("b=2; a=1")
+        );
+    }
+
+    #[test]
+    fn secure_cookie_is_not_sent_over_http() {
+        let mut jar = jar_with("https://example.com/", "id=1; Secure");
+        assert_eq!(jar.header_for(&url("http://example.com/")), None);
+    }
+
+    #[test]
+    fn expired_cookie_removes_the_stored_one() {
+        let mut jar = jar_with("https://example.com/", "id=1");
+        jar.store(&url("https://example.com/"), "id=1; Max-Age=0");
+        assert!(jar.cookies().is_empty());
+    }
+}
@@ -0,0 +1,301 @@
+use std::fmt::{Debug, Display};
+
+use md5::Md5;
+use rand::RngCore;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use url::Url;
+
+use crate::request_error::RequestError;
+use crate::request_message::RequestMessage;
+
+// the algorithms authorization answers, a challenge for another one is skipped
+const ALGORITHMS: [&str; 4] = ["MD5", "MD5-SESS", "SHA-256", "SHA-256-SESS"];
+
+#[derive(Clone, Deserialize)]
+pub struct DigestConfig {
+    username: String,
+    #[serde(default)]
+    password: String,
+}
+
+impl DigestConfig {
+    pub fn authorization(
+        &self,
+        request_message: &RequestMessage,
+        challenge: &str,
+    ) -> Result<String, RequestError> {
+        let mut cnonce_bytes = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut cnonce_bytes);
+        self.answer(request_message, challenge, &hex::encode(cnonce_bytes))
+    }
+
+    fn answer(
+        &self,
+        request_message: &RequestMessage,
+        challenge: &str,
+        cnonce: &str,
+    ) -> Result<String, RequestError> {
+        let auth_error = |message: &str| RequestError::AuthError {
+            message: message.to_owned(),
+        };
+        let params = challenge_params(challenge);
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                .map(|(_, v)| v.as_str())
+        };
+
+        let realm = param("realm").unwrap_or_default();
+        let nonce = param("nonce").ok_or(auth_error("digest challenge without nonce"))?;
+        let algorithm = param("algorithm").unwrap_or("MD5");
+        let hash: fn(&str) -> String = match algorithm.to_uppercase().as_str() {
+            "MD5" | "MD5-SESS" => |value| hex::encode(Md5::digest(value.as_bytes())),
+            "SHA-256" | "SHA-256-SESS" => |value| hex::encode(Sha256::digest(value.as_bytes())),
+            _ => {
+                return Err(auth_error(&format!(
+                    "unsupported digest algorithm {algorithm}"
+                )))
+            }
+        };
+        let qop = match param("qop") {
+            Some(qop) if qop.split(',').any(|q| q.trim() == "auth") => Some("auth"),
+            Some(qop) => return Err(auth_error(&format!("unsupported digest qop {qop}"))),
+            None => None,
+        };
+
+        let url = Url::parse(&request_message.url).map_err(|e| RequestError::InvalidUrl {
+            url: request_message.url.clone(),
+            message: e.to_string(),
+        })?;
+        let uri = match url.query() {
+            Some(query) => format!("{0}?{query}", url.path()),
+            None => url.path().to_owned(),
+        };
+
+        let nonce_count = "00000001";
+
+        let mut ha1 = hash(&format!("{0}:{realm}:{1}", self.username, self.password));
+        if algorithm.to_uppercase().ends_with("-SESS") {
+            ha1 = hash(&format!("{ha1}:{nonce}:{cnonce}"));
+        }
+        let ha2 = hash(&format!("{0}:{uri}", request_message.method));
+        let response = match qop {
+            Some(qop) => hash(&format!("{ha1}:{nonce}:{nonce_count}:{cnonce}:{qop}:{ha2}")),
+            None => hash(&format!("{ha1}:{nonce}:{ha2}")),
+        };
+
+        let mut authorization = format!(
+            "Digest username={0}, realm={1}, nonce={2}, uri={3}, algorithm={algorithm}, response=\"{response}\"",
+            quote(&self.username),
+            quote(realm),
+            quote(nonce),
+            quote(&uri)
+        );
+        if let Some(qop) = qop {
+            authorization.push_str(&format!(
+                ", qop={qop}, nc={nonce_count}, cnonce=\"{cnonce}\""
+            ));
+        }
+        if let Some(opaque) = param("opaque") {
+            authorization.push_str(&format!(", opaque={0}", quote(opaque)));
+        }
+        Ok(authorization)
+    }
+}
+
+// the client picks among the offered challenges, the first one it can answer is used
+pub fn find_challenge<'a>(www_authenticate: impl Iterator<Item = &'a str>) -> Option<&'a str> {
+    www_authenticate
+        .flat_map(challenges)
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("digest"))
+        .map(|(_, params)| params)
+        .find(|params| {
+            let algorithm = challenge_params(params)
+                .into_iter()
+                .find(|(k, _)| k.eq_ignore_ascii_case("algorithm"))
+                .map_or("MD5".to_owned(), |(_, v)| v.to_uppercase());
+            ALGORITHMS.contains(&algorithm.as_str())
+        })
+}
+
+// a header value may hold several challenges, each starts with a scheme not followed by =
+fn challenges(value: &str) -> Vec<(&str, &str)> {
+    let mut starts = Vec::new();
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut after_comma = true;
+    for (index, c) in value.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ',' if !quoted => after_comma = true,
+            c if after_comma && !c.is_whitespace() => {
+                after_comma = false;
+                let token = value[index..]
+                    .split(|c: char| c.is_whitespace() || c == '=' || c == ',')
+                    .next()
+                    .unwrap_or_default();
+                let rest = value[index + token.len()..].trim_start();
+                if !rest.starts_with('=') {
+                    starts.push(index);
+                }
+            }
+            _ => {}
+        }
+    }
+    starts
+        .iter()
+        .enumerate()
+        .map(|(i, &start)| {
+            let end = starts.get(i + 1).copied().unwrap_or(value.len());
+            let challenge = value[start..end].trim().trim_end_matches(',');
+            challenge
+                .split_once(' ')
+                .map_or((challenge, ""), |(scheme, params)| (scheme, params.trim()))
+        })
+        .collect()
+}
+
+fn challenge_params(challenge: &str) -> Vec<(String, String)> {
+    let mut params = Vec::new();
+    let mut rest = challenge.trim();
+    while let Some((key, after_key)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_owned();
+        let after_key = after_key.trim_start();
+        let (value, remaining) = match after_key.strip_prefix('"') {
+            Some(quoted) => unquote(quoted),
+            None => {
+                let end = after_key.find(',').unwrap_or(after_key.len());
+                (after_key[..end].trim().to_owned(), &after_key[end..])
+            }
+        };
+        params.push((key, value));
+        rest = remaining.trim_start().trim_start_matches(',');
+    }
+    params
+}
+
+// quoted-string values escape their quotes and backslashes, the hashes use the plain value
+fn quote(value: &str) -> String {
+    let escaped = value.replace('\\', "\\\\").replace('"', "\\\"");
+    format!("\"{escaped}\"")
+}
+
+// reads a quoted-string after its opening quote, returning the value and what follows it
+fn unquote(quoted: &str) -> (String, &str) {
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return (value, &quoted[index + 1..]),
+            '\\' => value.extend(chars.next().map(|(_, escaped)| escaped)),
+            c => value.push(c),
+        }
+    }
+    (value, "")
+}
+
+impl Debug for DigestConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DigestConfig")
+            .field("username", &self.username)
+            .field("password", &"***")
+            .finish()
+    }
+}
+
+impl Display for DigestConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "digest, username {0}, password ***", self.username)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the example of rfc 7616 section 3.9.1
+    const CHALLENGE: &str = "realm=\"http-auth@example.org\", qop=\"auth, auth-int\", \
+        algorithm={algorithm}, nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", \
+        opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\"";
+    const CNONCE: &str = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ";
+
+    fn answer(username: &str, algorithm: &str) -> String {
+        let config = DigestConfig {
+            username: username.to_owned(),
+            password: "Circle of Life".to_owned(),
+        };
+        let request_message = RequestMessage::from_text(
+            "method = \"GET\"\nhost = \"http://www.example.org\"\npath = \"/dir/index.html\"",
+        )
+        .unwrap()
+        .to_message()
+        .unwrap();
+        let challenge = CHALLENGE.replace("{algorithm}", algorithm);
+        config.answer(&request_message, &challenge, CNONCE).unwrap()
+    }
+
+    #[test]
+    fn answers_rfc_7616_md5_example() {
+        assert!(answer("Mufasa", "MD5").contains("response=\"8ca523f5e9506fed4657c9700eebdbec\""));
+    }
+
+    #[test]
+    fn answers_rfc_7616_sha256_example() {
+        assert_eq!(
+            answer("Mufasa", "SHA-256"),
+            "Digest username=\"Mufasa\", realm=\"http-auth@example.org\", \
+             nonce=\"7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v\", uri=\"/dir/index.html\", \
+             algorithm=SHA-256, \
+             response=\"753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1\", \
+             qop=auth, nc=00000001, cnonce=\"f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ\", \
+             opaque=\"FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS\""
+        );
+    }
+
+    #[test]
+    fn escapes_quotes_in_username() {
+        assert!(answer("a\"b\\c", "MD5").starts_with("Digest username=\"a\\\"b\\\\c\", "));
+    }
+
+    #[test]
+    fn picks_the_first_challenge_with_a_known_algorithm() {
+        let offered = [
+            "Basic realm=\"api\"",
+            "Digest realm=\"api\", algorithm=SHA-512-256, nonce=\"a\"",
+            "Digest realm=\"api\", algorithm=SHA-256, nonce=\"b\"",
+            "Digest realm=\"api\", nonce=\"c\"",
+        ];
+        assert_eq!(
+            find_challenge(offered.into_iter()),
+            Some("realm=\"api\", algorithm=SHA-256, nonce=\"b\"")
+        );
+        assert_eq!(find_challenge(offered[..2].iter().copied()), None);
+    }
+
+    #[test]
+    fn splits_challenges_sharing_one_header() {
+        let header = "Digest realm=\"a, Digest b\", algorithm=SHA-512-256, nonce=\"a\", \
+                      Basic realm=\"api\", Digest realm=\"api\", algorithm=MD5-sess, nonce=\"b\"";
+        assert_eq!(
+            find_challenge([header].into_iter()),
+            Some("realm=\"api\", algorithm=MD5-sess, nonce=\"b\"")
+        );
+    }
+
+    #[test]
+    fn reads_escaped_challenge_values() {
+        let params = challenge_params("realm=\"a \\\"b\\\" c\", nonce=xyz, qop=\"auth\"");
+        assert_eq!(
+            params,
+            vec![
+                ("realm".to_owned(), "a \"b\" c".to_owned()),
+                ("nonce".to_owned(), "xyz".to_owned()),
+                ("qop".to_owned(), "auth".to_owned()),
+            ]
+        );
+    }
+}
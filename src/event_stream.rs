@@ -0,0 +1,454 @@
+use std::{
+    fmt::Display,
+    mem,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use async_std::{io::ReadExt, task};
+use chrono::{DateTime, Local};
+use iced::futures::{future, SinkExt};
+use iced::subscription::{self, Subscription};
+use isahc::AsyncBody;
+
+use crate::http_client;
+use crate::request_error::RequestError;
+use crate::request_message::{RequestMessage, RequestMessageBuilder};
+use crate::session::Session;
+
+// what browsers wait before reconnecting when the server sent no retry field
+const DEFAULT_RETRY: Duration = Duration::from_secs(3);
+const MAX_EVENTS: usize = 1000;
+
+// every stream gets its own subscription id, so iced drops the old one
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+pub struct EventStream {
+    body: AsyncBody,
+    parser: EventParser,
+}
+
+#[derive(Debug, Clone)]
+pub struct ServerEvent {
+    pub id: Option<String>,
+    pub event: String,
+    pub data: String,
+    pub received_at: DateTime<Local>,
+}
+
+#[derive(Debug, Clone)]
+pub enum StreamUpdate {
+    Event(ServerEvent),
+    Retry(Duration),
+    // the connection was lost, reconnecting makes sense
+    Ended(Option<String>),
+    // the server did not answer with an event stream
+    Failed(String),
+}
+
+enum Connection {
+    Open(EventStream),
+    Reconnect {
+        request: Box<RequestMessage>,
+        delay: Duration,
+    },
+}
+
+pub struct EventLog {
+    pub events: Vec<ServerEvent>,
+    pub status: String,
+    pub running: bool,
+    last_event_id: Option<String>,
+    retry: Duration,
+    id: u64,
+    connection: Arc<Mutex<Option<Connection>>>,
+    request: RequestMessageBuilder,
+}
+
+#[derive(Default)]
+struct EventParser {
+    line: Vec<u8>,
+    after_cr: bool,
+    started: bool,
+    event: String,
+    data: String,
+    last_event_id: Option<String>,
+}
+
+impl EventStream {
+    pub fn new(body: AsyncBody) -> Self {
+        EventStream {
+            body,
+            parser: EventParser::default(),
+        }
+    }
+}
+
+pub fn is_event_stream(content_type: Option<&str>) -> bool {
+    content_type.is_some_and(|content_type| {
+        content_type
+            .split(';')
+            .next()
+            .is_some_and(|mime| mime.trim().eq_ignore_ascii_case("text/event-stream"))
+    })
+}
+
+impl EventLog {
+    pub fn new(stream: EventStream, url: &str, request: RequestMessageBuilder) -> Self {
+        EventLog {
+            events: Vec::new(),
+            status: format!("streaming events from {url}"),
+            running: true,
+            last_event_id: None,
+            retry: DEFAULT_RETRY,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            connection: Arc::new(Mutex::new(Some(Connection::Open(stream)))),
+            request,
+        }
+    }
+
+    pub fn subscription(&self, session: &Session) -> Subscription<StreamUpdate> {
+        if !self.running {
+            return Subscription::none();
+        }
+        let connection = self.connection.clone();
+        let session = session.clone();
+        subscription::channel(self.id, 100, move |mut output| async move {
+            let connection = connection.lock().ok().and_then(|mut c| c.take());
+            let update = match connection {
+                Some(Connection::Open(stream)) => read_events(stream, &mut output).await,
+                Some(Connection::Reconnect { request, delay }) => {
+                    task::sleep(delay).await;
+                    match connect(*request, &session).await {
+                        Ok(stream) => read_events(stream, &mut output).await,
+                        Err(update) => update,
+                    }
+                }
+                None => StreamUpdate::Ended(None),
+            };
+            let _ = output.send(update).await;
+            future::pending().await
+        })
+    }
+
+    pub fn update(&mut self, update: StreamUpdate, reconnect: bool) {
+        match update {
+            StreamUpdate::Event(event) => {
+                if event.id.is_some() {
+                    self.last_event_id = event.id.clone();
+                }
+                if self.events.len() == MAX_EVENTS {
+                    self.events.remove(0);
+                }
+                self.events.push(event);
+            }
+            StreamUpdate::Retry(retry) => self.retry = retry,
+            StreamUpdate::Ended(error) => {
+                let reason = match error {
+                    Some(error) => format!("connection lost: {error}"),
+                    None => "the server closed the stream".to_owned(),
+                };
+                if reconnect && self.running {
+                    match self.reconnect() {
+                        Ok(()) => {
+                            self.status =
+                                format!("{reason}, reconnecting in {0}ms", self.retry.as_millis())
+                        }
+                        Err(e) => self.stop(format!("{reason}, could not reconnect: {e}")),
+                    }
+                } else {
+                    self.stop(reason);
+                }
+            }
+            StreamUpdate::Failed(error) => self.stop(error),
+        }
+    }
+
+    pub fn stop(&mut self, status: String) {
+        self.running = false;
+        self.status = status;
+    }
+
+    fn reconnect(&mut self) -> Result<(), RequestError> {
+        let mut request = self.request.to_message()?;
+        if let Some(id) = &self.last_event_id {
+            request.set_header("Last-Event-ID", id);
+        }
+        self.id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+        self.connection = Arc::new(Mutex::new(Some(Connection::Reconnect {
+            request: Box::new(request),
+            delay: self.retry,
+        })));
+        Ok(())
+    }
+}
+
+async fn connect(request: RequestMessage, session: &Session) -> Result<EventStream, StreamUpdate> {
+    let mut response = http_client::send(request, session)
+        .await
+        .map_err(|e| StreamUpdate::Ended(Some(e.to_string())))?;
+    response.event_stream.take().ok_or_else(|| {
+        StreamUpdate::Failed(format!(
+            "the server answered {0} without an event stream",
+            response.status
+        ))
+    })
+}
+
+async fn read_events(
+    mut stream: EventStream,
+    output: &mut iced::futures::channel::mpsc::Sender<StreamUpdate>,
+) -> StreamUpdate {
+    let mut buffer = [0u8; 8192];
+    loop {
+        match stream.body.read(&mut buffer).await {
+            Ok(0) => return StreamUpdate::Ended(None),
+            Ok(read) => {
+                for update in stream.parser.feed(&buffer[..read]) {
+                    if output.send(update).await.is_err() {
+                        return StreamUpdate::Ended(None);
+                    }
+                }
+            }
+            Err(e) => return StreamUpdate::Ended(Some(e.to_string())),
+        }
+    }
+}
+
+// follows the parsing rules of the html living standard, lines may end with
+// \n, \r or \r\n and a chunk can stop anywhere in a line
+impl EventParser {
+    fn feed(&mut self, bytes: &[u8]) -> Vec<StreamUpdate> {
+        let mut updates = Vec::new();
+        for &byte in bytes {
+            if mem::take(&mut self.after_cr) && byte == b'\n' {
+                continue;
+            }
+            match byte {
+                b'\n' => self.end_line(&mut updates),
+                b'\r' => {
+                    self.after_cr = true;
+                    self.end_line(&mut updates);
+                }
+                _ => self.line.push(byte),
+            }
+        }
+        updates
+    }
+
+    fn end_line(&mut self, updates: &mut Vec<StreamUpdate>) {
+        let line = String::from_utf8_lossy(&mem::take(&mut self.line)).into_owned();
+        let line = match mem::replace(&mut self.started, true) {
+            false => line.trim_start_matches('\u{feff}').to_owned(),
+            true => line,
+        };
+        if line.is_empty() {
+            self.dispatch(updates);
+            return;
+        }
+        if line.starts_with(':') {
+            return;
+        }
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line.as_str(), ""),
+        };
+        match field {
+            "event" => self.event = value.to_owned(),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => {
+                self.last_event_id = Some(value.to_owned()).filter(|id| !id.is_empty())
+            }
+            "retry" => {
+                if let Ok(retry) = value.parse() {
+                    updates.push(StreamUpdate::Retry(Duration::from_millis(retry)));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn dispatch(&mut self, updates: &mut Vec<StreamUpdate>) {
+        let event = mem::take(&mut self.event);
+        let mut data = mem::take(&mut self.data);
+        if data.is_empty() {
+            return;
+        }
+        data.pop();
+        updates.push(StreamUpdate::Event(ServerEvent {
+            id: self.last_event_id.clone(),
+            event: if event.is_empty() {
+                "message".to_owned()
+            } else {
+                event
+            },
+            data,
+            received_at: Local::now(),
+        }));
+    }
+}
+
+impl Display for ServerEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{0} {1}",
+            self.received_at.format("%H:%M:%S%.3f"),
+            self.event
+        )?;
+        if let Some(id) = &self.id {
+            write!(f, " id {id}")?;
+        }
+        write!(f, "\n{0}", self.data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(chunks: &[&str]) -> Vec<(Option<String>, String, String)> {
+        let mut parser = EventParser::default();
+        chunks
+            .iter()
+            .flat_map(|chunk| parser.feed(chunk.as_bytes()))
+            .filter_map(|update| match update {
+                StreamUpdate::Event(event) => Some((event.id, event.event, event.data)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn data(chunks: &[&str]) -> Vec<String> {
+        events(chunks)
+            .into_iter()
+            .map(|(_, _, data)| data)
+            .collect()
+    }
+
+    // the examples of the html living standard, section 9.2.5
+    #[test]
+    fn joins_data_lines() {
+        assert_eq!(
+            data(&["data: YHOO\ndata: +2\ndata: 10\n\n"]),
+            ["YHOO\n+2\n10"]
+        );
+    }
+
+    #[test]
+    fn keeps_last_event_id_until_reset() {
+        let events = events(&[
+            ": test stream\n\ndata: first event\nid: 1\n\ndata:second event\nid\n\ndata:  third event\n",
+        ]);
+        assert_eq!(
+            events,
+            [
+                (
+                    Some("1".to_owned()),
+                    "message".to_owned(),
+                    "first event".to_owned()
+                ),
+                (None, "message".to_owned(), "second event".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn dispatches_empty_data_and_drops_unfinished_event() {
+        assert_eq!(data(&["data\n\ndata\ndata\n\ndata:"]), ["", "\n"]);
+    }
+
+    #[test]
+    fn strips_one_space_after_colon() {
+        assert_eq!(
+            data(&["data:test\n\ndata: test\n\ndata:  test\n\n"]),
+            ["test", "test", " test"]
+        );
+    }
+
+    #[test]
+    fn accepts_any_line_ending_across_chunks() {
+        assert_eq!(
+            events(&["\u{feff}event: tick\r", "\ndata: a\r\rdata: b\n", "\n"]),
+            [
+                (None, "tick".to_owned(), "a".to_owned()),
+                (None, "message".to_owned(), "b".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reads_retry_field() {
+        let mut parser = EventParser::default();
+        let updates = parser.feed(b"retry: 500\nretry: soon\n");
+        assert!(matches!(
+            updates.as_slice(),
+            [StreamUpdate::Retry(retry)] if *retry == Duration::from_millis(500)
+        ));
+    }
+
+    fn event_log() -> EventLog {
+        let request = RequestMessage::from_text(
+            "method = \"GET\"\nhost = \"http://localhost\"\npath = \"/events\"",
+        )
+        .unwrap();
+        EventLog::new(
+            EventStream::new(AsyncBody::empty()),
+            "http://localhost/events",
+            request,
+        )
+    }
+
+    fn event(id: Option<&str>) -> StreamUpdate {
+        StreamUpdate::Event(ServerEvent {
+            id: id.map(str::to_owned),
+            event: "message".to_owned(),
+            data: String::new(),
+            received_at: Local::now(),
+        })
+    }
+
+    #[test]
+    fn reconnects_with_last_event_id_after_retry_delay() {
+        let mut log = event_log();
+        let first_id = log.id;
+        log.update(event(Some("7")), true);
+        log.update(event(None), true);
+        log.update(StreamUpdate::Retry(Duration::from_millis(250)), true);
+        log.update(StreamUpdate::Ended(None), true);
+
+        assert!(log.running);
+        assert_ne!(log.id, first_id);
+        assert_eq!(
+            log.status,
+            "the server closed the stream, reconnecting in 250ms"
+        );
+        let connection = log.connection.lock().unwrap().take();
+        let Some(Connection::Reconnect { request, delay }) = connection else {
+            panic!("no reconnection was prepared");
+        };
+        assert_eq!(delay, Duration::from_millis(250));
+        assert_eq!(
+            request.headers.get("Last-Event-ID").map(String::as_str),
+            Some("7")
+        );
+    }
+
+    #[test]
+    fn stops_when_reconnecting_is_off_or_the_server_refuses() {
+        let mut log = event_log();
+        log.update(StreamUpdate::Ended(Some("reset".to_owned())), false);
+        assert!(!log.running);
+        assert_eq!(log.status, "connection lost: reset");
+
+        let mut log = event_log();
+        log.update(StreamUpdate::Failed("not an event stream".to_owned()), true);
+        assert!(!log.running);
+    }
+}
@@ -43,18 +43,18 @@ impl FileTree {
             .collect()
     }
 
-    pub fn get_elements(&self) -> Element<Message> {
+    pub fn get_elements(&self) -> Element<'_, Message> {
         let elem: Vec<Element<Message>> = self.items.iter().map(|fi| fi.get_element()).collect();
         column(elem).into()
     }
 
     pub fn navigate(&mut self, path: &String) {
         println!("trying to navigate to {path}");
-        if path.starts_with(self.root.as_str()) == false {
+        if !path.starts_with(self.root.as_str()) {
             println!("could not navigate to {path}");
             return;
         }
-        
+
         let parent = Path::new(path).parent().unwrap_or(Path::new(&self.root));
         let back_path = if parent.starts_with(&self.root) {
             String::from(parent.to_str().unwrap_or(&self.root))
@@ -68,7 +68,7 @@ impl FileTree {
         }
     }
 
-    fn get_items(read_dir: ReadDir, back_path: &String) -> Result<Vec<FileTreeItem>, Error> {
+    fn get_items(read_dir: ReadDir, back_path: &str) -> Result<Vec<FileTreeItem>, Error> {
         let mut dir_items: Vec<FileTreeItem> = vec![];
         let mut file_items: Vec<FileTreeItem> = vec![];
 
@@ -97,14 +97,15 @@ impl FileTree {
                 }
             }
         }
-        dir_items.sort_by(|a, b| a.get_name().cmp(&b.get_name()));
-        
-        file_items.sort_by(|a, b| a.get_name().cmp(&b.get_name()));
+        dir_items.sort_by_key(|a| a.get_name());
+
+        file_items.sort_by_key(|a| a.get_name());
         file_items.append(&mut dir_items);
-        file_items.insert(0,
+        file_items.insert(
+            0,
             FileTreeItem::Control {
                 name: "..".to_string(),
-                path: back_path.clone(),
+                path: back_path.to_string(),
             },
         );
         Ok(file_items)
@@ -120,7 +121,7 @@ impl FileTreeItem {
         }
     }
 
-    fn get_element(&self) -> Element<Message> {
+    fn get_element(&self) -> Element<'_, Message> {
         match self {
             FileTreeItem::Directory { name, path } => button(text(format!("> {0}", name)))
                 .on_press(Message::FileTreeItemToogled(path.clone()))
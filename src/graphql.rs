@@ -0,0 +1,813 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::Display,
+    fs,
+};
+
+use chrono::Local;
+use graphql_parser::query::{
+    self, Definition, Directive, FragmentDefinition, OperationDefinition, Selection, SelectionSet,
+    Type, TypeCondition, Value, VariableDefinition,
+};
+use graphql_parser::Pos;
+use serde::{Deserialize, Serialize};
+
+use crate::http_client;
+use crate::request_body::RequestBody;
+use crate::request_message::{self, HttpVerb, RequestMessage};
+use crate::session::{self, Session};
+
+const SCHEMAS_FILE: &str = "graphql_schemas.json";
+// only what validation and completion need, descriptions are left out
+const INTROSPECTION_QUERY: &str = "query IntrospectionQuery {
+  __schema {
+    queryType { name }
+    mutationType { name }
+    subscriptionType { name }
+    types {
+      kind
+      name
+      fields(includeDeprecated: true) {
+        name
+        args { name defaultValue type { ...TypeRef } }
+        type { ...TypeRef }
+      }
+      possibleTypes { name }
+    }
+  }
+}
+fragment TypeRef on __Type {
+  kind name ofType { kind name ofType { kind name ofType { kind name ofType {
+    kind name ofType { kind name ofType { kind name ofType { kind name } } } } } } }
+}";
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GraphqlConfig {
+    query: String,
+    operation_name: Option<String>,
+    variables: Option<toml::Table>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Schema {
+    query_type: Option<NamedType>,
+    mutation_type: Option<NamedType>,
+    subscription_type: Option<NamedType>,
+    types: Vec<SchemaType>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct NamedType {
+    name: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SchemaType {
+    kind: String,
+    name: String,
+    fields: Option<Vec<SchemaField>>,
+    possible_types: Option<Vec<NamedType>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SchemaField {
+    name: String,
+    args: Vec<InputValue>,
+    #[serde(rename = "type")]
+    field_type: TypeRef,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct InputValue {
+    name: String,
+    #[serde(rename = "type")]
+    value_type: TypeRef,
+    default_value: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TypeRef {
+    kind: String,
+    name: Option<String>,
+    of_type: Option<Box<TypeRef>>,
+}
+
+#[derive(Deserialize)]
+struct IntrospectionResponse {
+    data: Option<IntrospectionData>,
+    #[serde(default)]
+    errors: Vec<GraphqlError>,
+}
+
+#[derive(Deserialize)]
+struct IntrospectionData {
+    #[serde(rename = "__schema")]
+    schema: Schema,
+}
+
+#[derive(Deserialize)]
+struct GraphqlError {
+    message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedSchema {
+    pub fetched_at: String,
+    pub schema: Schema,
+}
+
+// schemas by endpoint url, kept on disk so completion works before the next introspection
+#[derive(Debug, Default)]
+pub struct SchemaCache {
+    schemas: HashMap<String, CachedSchema>,
+}
+
+impl GraphqlConfig {
+    pub fn body(&self) -> String {
+        let mut body = serde_json::Map::new();
+        body.insert("query".to_owned(), self.query.clone().into());
+        if let Some(operation_name) = &self.operation_name {
+            body.insert("operationName".to_owned(), operation_name.clone().into());
+        }
+        if let Some(variables) = &self.variables {
+            let variables = toml::Value::Table(variables.clone());
+            body.insert(
+                "variables".to_owned(),
+                request_message::toml_to_json(&variables),
+            );
+        }
+        serde_json::Value::Object(body).to_string()
+    }
+
+    // without a schema only the syntax and the variables are checked
+    pub fn validate(&self, schema: Option<&Schema>) -> Vec<String> {
+        let document = match query::parse_query::<String>(&self.query) {
+            Ok(document) => document,
+            Err(e) => return vec![e.to_string().trim().to_owned()],
+        };
+        let operations: Vec<&OperationDefinition<String>> = document
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                Definition::Operation(operation) => Some(operation),
+                Definition::Fragment(_) => None,
+            })
+            .collect();
+        let operation = match (&self.operation_name, operations.as_slice()) {
+            (None, [operation]) => *operation,
+            (None, []) => return vec!["the query has no operation".to_owned()],
+            (None, _) => {
+                return vec!["the query has several operations, set operation_name".to_owned()]
+            }
+            (Some(name), _) => {
+                let named = operations
+                    .iter()
+                    .find(|operation| Operation::from(**operation).name == Some(name.as_str()));
+                match named {
+                    Some(operation) => *operation,
+                    None => return vec![format!("operation {name} is not in the query")],
+                }
+            }
+        };
+
+        let fragments = document
+            .definitions
+            .iter()
+            .filter_map(|definition| match definition {
+                Definition::Fragment(fragment) => Some((fragment.name.as_str(), fragment)),
+                Definition::Operation(_) => None,
+            })
+            .collect();
+        let mut validator = Validator {
+            schema,
+            fragments,
+            checked_fragments: HashSet::new(),
+            used_variables: Vec::new(),
+            errors: Vec::new(),
+        };
+        validator.operation(operation.into(), self.variables.as_ref());
+        validator.errors
+    }
+}
+
+struct Operation<'d> {
+    name: Option<&'d str>,
+    kind: &'static str,
+    position: Pos,
+    variables: &'d [VariableDefinition<'d, String>],
+    directives: &'d [Directive<'d, String>],
+    selection_set: &'d SelectionSet<'d, String>,
+}
+
+impl<'d> From<&'d OperationDefinition<'d, String>> for Operation<'d> {
+    fn from(operation: &'d OperationDefinition<'d, String>) -> Self {
+        match operation {
+            OperationDefinition::SelectionSet(selection_set) => Operation {
+                name: None,
+                kind: "query",
+                position: selection_set.span.0,
+                variables: &[],
+                directives: &[],
+                selection_set,
+            },
+            OperationDefinition::Query(query) => Operation {
+                name: query.name.as_deref(),
+                kind: "query",
+                position: query.position,
+                variables: &query.variable_definitions,
+                directives: &query.directives,
+                selection_set: &query.selection_set,
+            },
+            OperationDefinition::Mutation(mutation) => Operation {
+                name: mutation.name.as_deref(),
+                kind: "mutation",
+                position: mutation.position,
+                variables: &mutation.variable_definitions,
+                directives: &mutation.directives,
+                selection_set: &mutation.selection_set,
+            },
+            OperationDefinition::Subscription(subscription) => Operation {
+                name: subscription.name.as_deref(),
+                kind: "subscription",
+                position: subscription.position,
+                variables: &subscription.variable_definitions,
+                directives: &subscription.directives,
+                selection_set: &subscription.selection_set,
+            },
+        }
+    }
+}
+
+struct Validator<'d> {
+    schema: Option<&'d Schema>,
+    fragments: HashMap<&'d str, &'d FragmentDefinition<'d, String>>,
+    // a fragment gives the same errors wherever it is spread
+    checked_fragments: HashSet<&'d str>,
+    used_variables: Vec<(&'d str, Pos)>,
+    errors: Vec<String>,
+}
+
+impl<'d> Validator<'d> {
+    fn operation(&mut self, operation: Operation<'d>, values: Option<&toml::Table>) {
+        self.directives(operation.directives);
+        let root = match self.schema {
+            Some(schema) => match schema.root(operation.kind) {
+                Some(root) => Some(root),
+                None => {
+                    let message = format!("the schema has no {0} type", operation.kind);
+                    self.error(operation.position, message);
+                    return;
+                }
+            },
+            None => None,
+        };
+        self.selection_set(root, operation.selection_set);
+
+        for definition in operation.variables {
+            let type_name = base_type(&definition.var_type);
+            let unknown = self
+                .schema
+                .is_some_and(|schema| schema.type_named(type_name).is_none());
+            if unknown {
+                let message = format!("unknown type {type_name} for ${0}", definition.name);
+                self.error(definition.position, message);
+            }
+            let required = matches!(definition.var_type, Type::NonNullType(_))
+                && definition.default_value.is_none();
+            if required && !values.is_some_and(|v| v.contains_key(&definition.name)) {
+                let message = format!(
+                    "${0} of type {1} has no value in [graphql.variables]",
+                    definition.name, definition.var_type
+                );
+                self.error(definition.position, message);
+            }
+        }
+        let declared = |name: &str| operation.variables.iter().any(|d| d.name == name);
+        for (name, position) in std::mem::take(&mut self.used_variables) {
+            if !declared(name) {
+                self.error(
+                    position,
+                    format!("${name} is not declared by the operation"),
+                );
+            }
+        }
+        for name in values.into_iter().flat_map(|v| v.keys()) {
+            if !declared(name) {
+                self.errors
+                    .push(format!("variable {name} is not declared by the operation"));
+            }
+        }
+    }
+
+    // selections on an unknown type are only searched for variables
+    fn selection_set(
+        &mut self,
+        type_name: Option<&'d str>,
+        selection_set: &'d SelectionSet<'d, String>,
+    ) {
+        for selection in selection_set.items.iter() {
+            match selection {
+                Selection::Field(field) => self.field(type_name, field),
+                Selection::FragmentSpread(spread) => {
+                    self.directives(&spread.directives);
+                    let name = spread.fragment_name.as_str();
+                    let Some(fragment) = self.fragments.get(name).copied() else {
+                        self.error(spread.position, format!("unknown fragment {name}"));
+                        continue;
+                    };
+                    if self.checked_fragments.insert(name) {
+                        self.directives(&fragment.directives);
+                        let TypeCondition::On(condition) = &fragment.type_condition;
+                        let condition = self.type_condition(condition, fragment.position);
+                        self.selection_set(condition, &fragment.selection_set);
+                    }
+                }
+                Selection::InlineFragment(inline) => {
+                    self.directives(&inline.directives);
+                    let condition = match &inline.type_condition {
+                        Some(TypeCondition::On(condition)) => {
+                            self.type_condition(condition, inline.position)
+                        }
+                        None => type_name,
+                    };
+                    self.selection_set(condition, &inline.selection_set);
+                }
+            }
+        }
+    }
+
+    fn type_condition(&mut self, name: &'d str, position: Pos) -> Option<&'d str> {
+        let schema = self.schema?;
+        if schema.type_named(name).is_none() {
+            self.error(position, format!("unknown type {name}"));
+            return None;
+        }
+        Some(name)
+    }
+
+    fn field(&mut self, type_name: Option<&'d str>, field: &'d query::Field<'d, String>) {
+        self.directives(&field.directives);
+        for (_, value) in field.arguments.iter() {
+            self.variables_in(value, field.position);
+        }
+        let name = field.name.as_str();
+        let parent = self
+            .schema
+            .zip(type_name)
+            .and_then(|(schema, type_name)| Some((schema, schema.type_named(type_name)?)));
+        let field_type = match parent {
+            Some(_) if name == "__typename" => None,
+            // __schema and __type are answered by every server
+            Some(_) if name.starts_with("__") => None,
+            Some((schema, parent)) => match parent.field(name) {
+                Some(definition) => {
+                    self.arguments(definition, field);
+                    self.subfields(schema, definition, field)
+                }
+                None => {
+                    let names = parent.fields().iter().map(|f| f.name.as_str());
+                    let message = format!(
+                        "unknown field {name} on {0}{1}",
+                        parent.name,
+                        suggestion(name, names)
+                    );
+                    self.error(field.position, message);
+                    None
+                }
+            },
+            None => None,
+        };
+        self.selection_set(field_type, &field.selection_set);
+    }
+
+    fn arguments(&mut self, definition: &'d SchemaField, field: &'d query::Field<'d, String>) {
+        for (name, _) in field.arguments.iter() {
+            if !definition.args.iter().any(|arg| &arg.name == name) {
+                let names = definition.args.iter().map(|arg| arg.name.as_str());
+                let message = format!(
+                    "unknown argument {name} on {0}{1}",
+                    field.name,
+                    suggestion(name, names)
+                );
+                self.error(field.position, message);
+            }
+        }
+        for arg in definition.args.iter() {
+            let required = arg.value_type.kind == "NON_NULL" && arg.default_value.is_none();
+            if required && !field.arguments.iter().any(|(name, _)| name == &arg.name) {
+                let message = format!(
+                    "{0} needs the argument {1}: {2}",
+                    field.name, arg.name, arg.value_type
+                );
+                self.error(field.position, message);
+            }
+        }
+    }
+
+    // returns the type to check the subfields against
+    fn subfields(
+        &mut self,
+        schema: &'d Schema,
+        definition: &'d SchemaField,
+        field: &'d query::Field<'d, String>,
+    ) -> Option<&'d str> {
+        let type_name = definition.field_type.named();
+        let composite = schema
+            .type_named(type_name)
+            .is_some_and(SchemaType::is_composite);
+        let selected = !field.selection_set.items.is_empty();
+        if composite && !selected {
+            let message = format!(
+                "{0} returns {1} and needs a selection of its fields",
+                field.name, definition.field_type
+            );
+            self.error(field.position, message);
+        } else if !composite && selected {
+            let message = format!(
+                "{0} returns {1} which has no fields to select",
+                field.name, definition.field_type
+            );
+            self.error(field.position, message);
+        }
+        composite.then_some(type_name)
+    }
+
+    fn directives(&mut self, directives: &'d [Directive<'d, String>]) {
+        for directive in directives {
+            for (_, value) in directive.arguments.iter() {
+                self.variables_in(value, directive.position);
+            }
+        }
+    }
+
+    fn variables_in(&mut self, value: &'d Value<'d, String>, position: Pos) {
+        match value {
+            Value::Variable(name) => self.used_variables.push((name, position)),
+            Value::List(items) => items
+                .iter()
+                .for_each(|item| self.variables_in(item, position)),
+            Value::Object(fields) => fields
+                .values()
+                .for_each(|item| self.variables_in(item, position)),
+            _ => {}
+        }
+    }
+
+    fn error(&mut self, position: Pos, message: String) {
+        self.errors
+            .push(format!("{0}:{1} {message}", position.line, position.column));
+    }
+}
+
+fn base_type<'d>(var_type: &'d Type<'d, String>) -> &'d str {
+    match var_type {
+        Type::NamedType(name) => name,
+        Type::ListType(inner) | Type::NonNullType(inner) => base_type(inner),
+    }
+}
+
+fn suggestion<'s>(name: &str, candidates: impl Iterator<Item = &'s str>) -> String {
+    let lowercase = name.to_lowercase();
+    let max_distance = 2.max(name.len() / 3);
+    candidates
+        .map(|candidate| (distance(&lowercase, &candidate.to_lowercase()), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| format!(", did you mean {candidate}?"))
+        .unwrap_or_default()
+}
+
+// levenshtein distance
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+impl Schema {
+    // a path like "user.posts.ti" lists the fields of posts starting with "ti",
+    // from the query type unless the path starts with mutation or subscription
+    pub fn complete(&self, path: &str) -> Vec<String> {
+        let mut parts: Vec<&str> = path.split('.').map(str::trim).collect();
+        let kind = match parts.as_slice() {
+            [kind @ ("mutation" | "subscription"), _, ..] => *kind,
+            _ => "query",
+        };
+        if kind != "query" {
+            parts.remove(0);
+        }
+        let prefix = parts.pop().unwrap_or_default().to_lowercase();
+
+        let mut current = self.root(kind).and_then(|root| self.type_named(root));
+        for part in parts {
+            current = current
+                .and_then(|parent| parent.field(part))
+                .and_then(|field| self.type_named(field.field_type.named()));
+        }
+        let Some(current) = current else {
+            return Vec::new();
+        };
+        let fields = current
+            .fields()
+            .iter()
+            .filter(|field| field.name.to_lowercase().starts_with(&prefix))
+            .map(|field| field.to_string());
+        // unions have no fields of their own, only the types they can be
+        let possible_types = current
+            .possible_types
+            .iter()
+            .flatten()
+            .filter(|_| current.fields().is_empty())
+            .filter(|possible| possible.name.to_lowercase().starts_with(&prefix))
+            .map(|possible| format!("... on {0}", possible.name));
+        fields.chain(possible_types).collect()
+    }
+
+    fn root(&self, kind: &str) -> Option<&str> {
+        let root = match kind {
+            "mutation" => &self.mutation_type,
+            "subscription" => &self.subscription_type,
+            _ => &self.query_type,
+        };
+        root.as_ref().map(|root| root.name.as_str())
+    }
+
+    fn type_named(&self, name: &str) -> Option<&SchemaType> {
+        self.types.iter().find(|t| t.name == name)
+    }
+}
+
+impl SchemaType {
+    fn fields(&self) -> &[SchemaField] {
+        self.fields.as_deref().unwrap_or_default()
+    }
+
+    fn field(&self, name: &str) -> Option<&SchemaField> {
+        self.fields().iter().find(|field| field.name == name)
+    }
+
+    fn is_composite(&self) -> bool {
+        matches!(self.kind.as_str(), "OBJECT" | "INTERFACE" | "UNION")
+    }
+}
+
+impl TypeRef {
+    fn named(&self) -> &str {
+        match (&self.name, &self.of_type) {
+            (Some(name), _) => name,
+            (None, Some(of_type)) => of_type.named(),
+            (None, None) => "",
+        }
+    }
+}
+
+impl SchemaCache {
+    pub fn load() -> SchemaCache {
+        let schemas = session::config_path(SCHEMAS_FILE)
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        SchemaCache { schemas }
+    }
+
+    pub fn get(&self, endpoint: &str) -> Option<&CachedSchema> {
+        self.schemas.get(endpoint)
+    }
+
+    pub fn insert(&mut self, endpoint: &str, schema: Schema) {
+        let cached = CachedSchema {
+            fetched_at: Local::now().format("%Y-%m-%d %H:%M").to_string(),
+            schema,
+        };
+        self.schemas.insert(endpoint.to_owned(), cached);
+        self.save();
+    }
+
+    fn save(&self) {
+        let Some(path) = session::config_path(SCHEMAS_FILE) else {
+            println!("could not find a config folder to save the schemas");
+            return;
+        };
+        let result = serde_json::to_string(&self.schemas)
+            .map_err(|e| e.to_string())
+            .and_then(|content| {
+                path.parent()
+                    .map_or(Ok(()), fs::create_dir_all)
+                    .and_then(|_| fs::write(&path, content))
+                    .map_err(|e| e.to_string())
+            });
+        if let Err(e) = result {
+            println!("could not save schemas in {0}: {e}", path.display());
+        }
+    }
+}
+
+// the introspection query goes out like the request itself, with its headers, auth and tls
+pub async fn introspect(mut request: RequestMessage, session: &Session) -> Result<Schema, String> {
+    request.method = HttpVerb::POST;
+    request.body =
+        RequestBody::Text(serde_json::json!({ "query": INTROSPECTION_QUERY }).to_string());
+    request.set_header("Content-Type", "application/json");
+    request.save_to = None;
+    request.assert = None;
+
+    let mut response = http_client::send(request, session)
+        .await
+        .map_err(|e| e.to_string())?;
+    let text = match response.body_stream.take() {
+        Some(stream) => stream.read_to_string().await?,
+        None => String::new(),
+    };
+    match serde_json::from_str::<IntrospectionResponse>(&text) {
+        Ok(IntrospectionResponse {
+            data: Some(data), ..
+        }) => Ok(data.schema),
+        Ok(IntrospectionResponse { errors, .. }) if !errors.is_empty() => {
+            let messages: Vec<String> = errors.into_iter().map(|e| e.message).collect();
+            Err(format!("introspection failed: {0}", messages.join(", ")))
+        }
+        _ => Err(format!(
+            "the endpoint answered {0} without a schema",
+            response.status
+        )),
+    }
+}
+
+impl Display for GraphqlConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{0}", self.query.trim())?;
+        if let Some(operation_name) = &self.operation_name {
+            writeln!(f, "operation {operation_name}")?;
+        }
+        if let Some(variables) = &self.variables {
+            let variables = toml::Value::Table(variables.clone());
+            write!(
+                f,
+                "variables {0}",
+                request_message::toml_to_json(&variables)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for CachedSchema {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{0} types, fetched {1}",
+            self.schema.types.len(),
+            self.fetched_at
+        )
+    }
+}
+
+impl Display for SchemaField {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{0}", self.name)?;
+        if !self.args.is_empty() {
+            let args: Vec<String> = self
+                .args
+                .iter()
+                .map(|arg| match &arg.default_value {
+                    Some(default) => format!("{0}: {1} = {default}", arg.name, arg.value_type),
+                    None => format!("{0}: {1}", arg.name, arg.value_type),
+                })
+                .collect();
+            write!(f, "({0})", args.join(", "))?;
+        }
+        write!(f, ": {0}", self.field_type)
+    }
+}
+
+impl Display for TypeRef {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.kind.as_str(), &self.of_type) {
+            ("NON_NULL", Some(of_type)) => write!(f, "{of_type}!"),
+            ("LIST", Some(of_type)) => write!(f, "[{of_type}]"),
+            _ => write!(f, "{0}", self.name.as_deref().unwrap_or_default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema() -> Schema {
+        let scalar =
+            |name: &str| serde_json::json!({"kind": "SCALAR", "name": name, "ofType": null});
+        let non_null =
+            |inner| serde_json::json!({"kind": "NON_NULL", "name": null, "ofType": inner});
+        serde_json::from_value(serde_json::json!({
+            "queryType": {"name": "Query"},
+            "mutationType": null,
+            "subscriptionType": null,
+            "types": [
+                {"kind": "OBJECT", "name": "Query", "fields": [{
+                    "name": "user",
+                    "args": [{"name": "id", "type": non_null(scalar("ID")), "defaultValue": null}],
+                    "type": {"kind": "OBJECT", "name": "User", "ofType": null},
+                }]},
+                {"kind": "OBJECT", "name": "User", "fields": [
+                    {"name": "name", "args": [], "type": scalar("String")},
+                    {"name": "friends", "args": [], "type": {
+                        "kind": "LIST", "name": null,
+                        "ofType": {"kind": "OBJECT", "name": "User", "ofType": null},
+                    }},
+                ]},
+                {"kind": "SCALAR", "name": "ID"},
+                {"kind": "SCALAR", "name": "String"},
+            ],
+        }))
+        .unwrap()
+    }
+
+    fn errors(config: &str, schema: Option<&Schema>) -> Vec<String> {
+        toml::from_str::<GraphqlConfig>(config)
+            .unwrap()
+            .validate(schema)
+    }
+
+    #[test]
+    fn accepts_a_valid_query() {
+        let config = r#"
+            query = "query User($id: ID!) { user(id: $id) { name friends { ...Names } } } fragment Names on User { name __typename }"
+            variables = { id = "1" }
+        "#;
+        assert!(errors(config, Some(&schema())).is_empty());
+    }
+
+    #[test]
+    fn reports_syntax_errors_without_a_schema() {
+        assert_eq!(errors(r#"query = "{ user { name }""#, None).len(), 1);
+        assert!(errors(r#"query = "{ anything { goes } }""#, None).is_empty());
+    }
+
+    #[test]
+    fn suggests_close_field_names() {
+        let config = r#"query = "{ user(id: 1) { nmae } }""#;
+        assert_eq!(
+            errors(config, Some(&schema())),
+            ["1:17 unknown field nmae on User, did you mean name?"]
+        );
+    }
+
+    #[test]
+    fn checks_arguments_and_selections() {
+        let config = r#"query = "{ user(uid: 1) { name { first } } }""#;
+        assert_eq!(
+            errors(config, Some(&schema())),
+            [
+                "1:3 unknown argument uid on user, did you mean id?",
+                "1:3 user needs the argument id: ID!",
+                "1:18 name returns String which has no fields to select",
+            ]
+        );
+        let config = r#"query = "{ user(id: 1) }""#;
+        assert_eq!(
+            errors(config, Some(&schema())),
+            ["1:3 user returns User and needs a selection of its fields"]
+        );
+    }
+
+    #[test]
+    fn checks_variables_against_the_operation() {
+        let config = r#"
+            query = "query User($id: ID!) { user(id: $other) { name } }"
+            variables = { extra = 1 }
+        "#;
+        assert_eq!(
+            errors(config, None),
+            [
+                "1:12 $id of type ID! has no value in [graphql.variables]",
+                "1:24 $other is not declared by the operation",
+                "variable extra is not declared by the operation",
+            ]
+        );
+    }
+
+    #[test]
+    fn needs_operation_name_for_several_operations() {
+        let config =
+            r#"query = "query A { user(id: 1) { name } } query B { user(id: 2) { name } }""#;
+        assert_eq!(
+            errors(config, None),
+            ["the query has several operations, set operation_name"]
+        );
+        let named = format!("{config}\noperation_name = \"C\"");
+        assert_eq!(errors(&named, None), ["operation C is not in the query"]);
+    }
+}
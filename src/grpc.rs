@@ -0,0 +1,720 @@
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use async_std::io::ReadExt;
+use iced::futures::{channel::mpsc::Sender, future, SinkExt};
+use iced::subscription::{self, Subscription};
+use isahc::{http::Response, AsyncBody, ResponseExt};
+use prost::Message as _;
+use prost_reflect::{DescriptorPool, DynamicMessage, MessageDescriptor, SerializeOptions};
+use prost_types::FileDescriptorProto;
+use serde::Deserialize;
+use url::Url;
+
+use crate::http_client;
+use crate::request_body::RequestBody;
+use crate::request_error::RequestError;
+use crate::request_message::{self, HttpVersion, RequestMessage};
+use crate::response_message::ResponseMessage;
+use crate::session::Session;
+
+// tried in order, older servers only offer the alpha version
+const REFLECTION_PATHS: [&str; 2] = [
+    "/grpc.reflection.v1.ServerReflection/ServerReflectionInfo",
+    "/grpc.reflection.v1alpha.ServerReflection/ServerReflectionInfo",
+];
+const STATUS_NAMES: [&str; 17] = [
+    "OK",
+    "CANCELLED",
+    "UNKNOWN",
+    "INVALID_ARGUMENT",
+    "DEADLINE_EXCEEDED",
+    "NOT_FOUND",
+    "ALREADY_EXISTS",
+    "PERMISSION_DENIED",
+    "RESOURCE_EXHAUSTED",
+    "FAILED_PRECONDITION",
+    "ABORTED",
+    "OUT_OF_RANGE",
+    "UNIMPLEMENTED",
+    "INTERNAL",
+    "UNAVAILABLE",
+    "DATA_LOSS",
+    "UNAUTHENTICATED",
+];
+const UNIMPLEMENTED: u32 = 12;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct GrpcConfig {
+    proto: Option<String>,
+    #[serde(default)]
+    import_paths: Vec<String>,
+    service: String,
+    method: String,
+    message: Option<toml::Table>,
+}
+
+// the response of a call, its messages are read while they are displayed
+pub struct GrpcStream {
+    response: Response<AsyncBody>,
+    output: MessageDescriptor,
+}
+
+#[derive(Debug, Clone)]
+pub enum GrpcUpdate {
+    Message(String),
+    Finished {
+        status: String,
+        trailers: Vec<String>,
+    },
+    Failed(String),
+}
+
+pub struct GrpcCall {
+    pub running: bool,
+    messages: usize,
+    status: Option<String>,
+    started_at: Instant,
+    finished_in: Option<Duration>,
+    id: u64,
+    stream: Arc<Mutex<Option<GrpcStream>>>,
+}
+
+// grpc.reflection.v1 messages, only the parts used to find descriptors
+#[derive(Clone, PartialEq, prost::Message)]
+struct ReflectionRequest {
+    #[prost(string, tag = "1")]
+    host: String,
+    #[prost(oneof = "ReflectionQuery", tags = "3, 4, 7")]
+    query: Option<ReflectionQuery>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+enum ReflectionQuery {
+    #[prost(string, tag = "3")]
+    FileByFilename(String),
+    #[prost(string, tag = "4")]
+    FileContainingSymbol(String),
+    #[prost(string, tag = "7")]
+    ListServices(String),
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ReflectionResponse {
+    #[prost(oneof = "ReflectionAnswer", tags = "4, 6, 7")]
+    answer: Option<ReflectionAnswer>,
+}
+
+#[derive(Clone, PartialEq, prost::Oneof)]
+enum ReflectionAnswer {
+    #[prost(message, tag = "4")]
+    FileDescriptors(FileDescriptorResponse),
+    #[prost(message, tag = "6")]
+    Services(ListServiceResponse),
+    #[prost(message, tag = "7")]
+    Error(ErrorResponse),
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct FileDescriptorResponse {
+    #[prost(bytes = "vec", repeated, tag = "1")]
+    file_descriptor_proto: Vec<Vec<u8>>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ListServiceResponse {
+    #[prost(message, repeated, tag = "1")]
+    service: Vec<ServiceResponse>,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ServiceResponse {
+    #[prost(string, tag = "1")]
+    name: String,
+}
+
+#[derive(Clone, PartialEq, prost::Message)]
+struct ErrorResponse {
+    #[prost(int32, tag = "1")]
+    error_code: i32,
+    #[prost(string, tag = "2")]
+    error_message: String,
+}
+
+impl GrpcConfig {
+    pub fn path(&self) -> String {
+        format!("/{0}/{1}", self.service, self.method)
+    }
+
+    pub fn relative_to(&mut self, dir: &Path) {
+        for path in self.proto.iter_mut().chain(self.import_paths.iter_mut()) {
+            *path = dir.join(&*path).to_string_lossy().to_string();
+        }
+    }
+}
+
+impl GrpcStream {
+    async fn read(mut self, output: &mut Sender<GrpcUpdate>) -> GrpcUpdate {
+        let mut buffer = Vec::new();
+        let mut chunk = [0u8; 8192];
+        loop {
+            match self.response.body_mut().read(&mut chunk).await {
+                Ok(0) => break,
+                Ok(read) => buffer.extend_from_slice(&chunk[..read]),
+                Err(e) => return GrpcUpdate::Failed(e.to_string()),
+            }
+            loop {
+                let message = match next_message(&mut buffer) {
+                    Ok(Some(message)) => decode(self.output.clone(), &message),
+                    Ok(None) => break,
+                    Err(e) => return GrpcUpdate::Failed(e),
+                };
+                if output.send(GrpcUpdate::Message(message)).await.is_err() {
+                    return GrpcUpdate::Failed("stopped".to_owned());
+                }
+            }
+        }
+        if !buffer.is_empty() {
+            return GrpcUpdate::Failed("the response ends inside a message".to_owned());
+        }
+        // the trailers have arrived once the body is read to the end
+        let trailers = self
+            .response
+            .trailer()
+            .try_get()
+            .map(|trailers| {
+                trailers
+                    .iter()
+                    .map(|(name, value)| {
+                        format!("{name}: {0}", String::from_utf8_lossy(value.as_bytes()))
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        GrpcUpdate::Finished {
+            status: status(&self.response),
+            trailers,
+        }
+    }
+}
+
+impl GrpcCall {
+    pub fn new(stream: GrpcStream) -> Self {
+        GrpcCall {
+            running: true,
+            messages: 0,
+            status: None,
+            started_at: Instant::now(),
+            finished_in: None,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            stream: Arc::new(Mutex::new(Some(stream))),
+        }
+    }
+
+    pub fn subscription(&self) -> Subscription<GrpcUpdate> {
+        if !self.running {
+            return Subscription::none();
+        }
+        let stream = self.stream.clone();
+        subscription::channel(self.id, 100, move |mut output| async move {
+            let stream = stream.lock().ok().and_then(|mut s| s.take());
+            let update = match stream {
+                Some(stream) => stream.read(&mut output).await,
+                None => GrpcUpdate::Failed("the call has ended".to_owned()),
+            };
+            let _ = output.send(update).await;
+            future::pending().await
+        })
+    }
+
+    // the messages go to the body as they arrive, the trailers to the headers
+    pub fn update(&mut self, update: GrpcUpdate, response: &mut ResponseMessage) {
+        match update {
+            GrpcUpdate::Message(message) => {
+                self.messages += 1;
+                if !response.body.is_empty() {
+                    response.body.push_str("\n\n");
+                }
+                response.body.push_str(&message);
+            }
+            GrpcUpdate::Finished { status, trailers } => {
+                response.headers.extend(trailers);
+                self.finish(status, &mut response.body);
+            }
+            GrpcUpdate::Failed(error) => self.finish(error, &mut response.body),
+        }
+    }
+
+    pub fn stop(&mut self, body: &mut String) {
+        self.finish("stopped".to_owned(), body);
+    }
+
+    fn finish(&mut self, status: String, body: &mut String) {
+        if self.running {
+            self.running = false;
+            self.finished_in = Some(self.started_at.elapsed());
+            if !body.is_empty() {
+                body.push_str("\n\n");
+            }
+            body.push_str(&status);
+            self.status = status.lines().next().map(str::to_owned);
+        }
+    }
+}
+
+pub async fn send(
+    mut request: RequestMessage,
+    session: &Session,
+) -> Result<ResponseMessage, RequestError> {
+    let Some(config) = request.grpc.take() else {
+        return Err(RequestError::BuildError {
+            property_name: "grpc".to_owned(),
+        });
+    };
+    // grpc needs http/2 and cleartext servers rarely offer an upgrade
+    request.http_version = match request.url.starts_with("https://") {
+        true => HttpVersion::Http2,
+        false => HttpVersion::H2c,
+    };
+    request.set_header("Content-Type", "application/grpc");
+    request.set_header("TE", "trailers");
+    if let Some(timeout) = request.timeout {
+        // the server is told the deadline so it can give up as well
+        request.set_header("grpc-timeout", &grpc_timeout(timeout));
+    }
+
+    let pool = match &config.proto {
+        Some(proto) => compile(proto, &config.import_paths)?,
+        None => reflect(&request, &config.service, session).await?,
+    };
+    let invalid = |property_name: &str, message: String| RequestError::InvalidValue {
+        property_name: property_name.to_owned(),
+        message,
+    };
+    let Some(service) = pool.get_service_by_name(&config.service) else {
+        let known: Vec<String> = pool.services().map(|s| s.full_name().to_owned()).collect();
+        return Err(invalid(
+            "grpc.service",
+            format!(
+                "unknown service {0}, the definitions have {1}",
+                config.service,
+                known.join(", ")
+            ),
+        ));
+    };
+    let Some(method) = service.methods().find(|m| m.name() == config.method) else {
+        let known: Vec<String> = service.methods().map(|m| m.name().to_owned()).collect();
+        return Err(invalid(
+            "grpc.method",
+            format!(
+                "{0} has no method {1}, it has {2}",
+                config.service,
+                config.method,
+                known.join(", ")
+            ),
+        ));
+    };
+    if method.is_client_streaming() {
+        return Err(invalid(
+            "grpc.method",
+            format!(
+                "{0} takes a stream of messages, only unary and server streaming methods can be called",
+                config.method
+            ),
+        ));
+    }
+    let message = encode(method.input(), config.message.as_ref())
+        .map_err(|message| invalid("grpc.message", message))?;
+
+    let started_at = Instant::now();
+    let response = call(&request, request.url.clone(), &message, session).await?;
+    let time_in_ms = started_at.elapsed().as_millis();
+
+    let mut response_message = http_client::response_head(&request, &response, time_in_ms);
+    response_message.method = "gRPC".to_owned();
+    response_message.grpc_stream = Some(GrpcStream {
+        response,
+        output: method.output(),
+    });
+    Ok(response_message)
+}
+
+fn compile(proto: &str, import_paths: &[String]) -> Result<DescriptorPool, RequestError> {
+    let invalid = |message: String| RequestError::InvalidValue {
+        property_name: "grpc.proto".to_owned(),
+        message,
+    };
+    // imports are looked up next to the proto file first
+    let proto_dir = Path::new(proto)
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let mut compiler = protox::Compiler::new(
+        std::iter::once(proto_dir.to_path_buf()).chain(import_paths.iter().map(PathBuf::from)),
+    )
+    .map_err(|e| invalid(e.to_string()))?;
+    // the imported files are kept too, the pool needs every type the service uses
+    compiler
+        .include_imports(true)
+        .open_file(proto)
+        .map_err(|e| invalid(e.to_string()))?;
+    Ok(compiler.descriptor_pool())
+}
+
+// the service file is asked for first, then every import the pool does not have yet
+async fn reflect(
+    request: &RequestMessage,
+    service: &str,
+    session: &Session,
+) -> Result<DescriptorPool, RequestError> {
+    let invalid = |message: String| RequestError::InvalidValue {
+        property_name: "grpc".to_owned(),
+        message: format!("server reflection failed, {message}, set proto to use a local file"),
+    };
+    let mut files = Vec::new();
+    let mut known = HashSet::new();
+    let mut queue = vec![ReflectionQuery::FileContainingSymbol(service.to_owned())];
+    while let Some(query) = queue.pop() {
+        let descriptors = match reflection_call(request, query, session).await? {
+            ReflectionAnswer::FileDescriptors(response) => response.file_descriptor_proto,
+            ReflectionAnswer::Error(error) if files.is_empty() => {
+                let services = list_services(request, session).await.unwrap_or_default();
+                return Err(invalid(format!(
+                    "{0}, the server offers {1}",
+                    error.error_message,
+                    services.join(", ")
+                )));
+            }
+            ReflectionAnswer::Error(error) => return Err(invalid(error.error_message)),
+            ReflectionAnswer::Services(_) => Vec::new(),
+        };
+        for bytes in descriptors {
+            let file = FileDescriptorProto::decode(bytes.as_slice())
+                .map_err(|e| invalid(e.to_string()))?;
+            if !known.insert(file.name().to_owned()) {
+                continue;
+            }
+            for dependency in file.dependency.iter() {
+                if !known.contains(dependency) {
+                    queue.push(ReflectionQuery::FileByFilename(dependency.clone()));
+                }
+            }
+            files.push(file);
+        }
+    }
+    let mut pool = DescriptorPool::new();
+    pool.add_file_descriptor_protos(files)
+        .map_err(|e| invalid(e.to_string()))?;
+    Ok(pool)
+}
+
+async fn list_services(
+    request: &RequestMessage,
+    session: &Session,
+) -> Result<Vec<String>, RequestError> {
+    let query = ReflectionQuery::ListServices(String::new());
+    match reflection_call(request, query, session).await? {
+        ReflectionAnswer::Services(response) => {
+            Ok(response.service.into_iter().map(|s| s.name).collect())
+        }
+        _ => Ok(Vec::new()),
+    }
+}
+
+async fn reflection_call(
+    request: &RequestMessage,
+    query: ReflectionQuery,
+    session: &Session,
+) -> Result<ReflectionAnswer, RequestError> {
+    let invalid = |message: String| RequestError::InvalidValue {
+        property_name: "grpc".to_owned(),
+        message: format!("server reflection failed, {message}, set proto to use a local file"),
+    };
+    let host = Url::parse(&request.url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_owned))
+        .unwrap_or_default();
+    let message = ReflectionRequest {
+        host,
+        query: Some(query),
+    }
+    .encode_to_vec();
+
+    let mut status = None;
+    for path in REFLECTION_PATHS {
+        let url = replace_path(&request.url, path);
+        let mut response = call(request, url, &message, session).await?;
+        let mut body = Vec::new();
+        response
+            .body_mut()
+            .read_to_end(&mut body)
+            .await
+            .map_err(|e| invalid(e.to_string()))?;
+        status = grpc_status(&response);
+        if status == Some(UNIMPLEMENTED) {
+            continue;
+        }
+        let messages = messages(body).map_err(invalid)?;
+        if let Some(answer) = messages.first() {
+            let response = ReflectionResponse::decode(answer.as_slice())
+                .map_err(|e| invalid(e.to_string()))?;
+            if let Some(answer) = response.answer {
+                return Ok(answer);
+            }
+        }
+        break;
+    }
+    let status = match status {
+        Some(code) => format!("grpc-status {code} {0}", status_name(code)),
+        None => "no answer".to_owned(),
+    };
+    Err(invalid(status))
+}
+
+// reflection calls too go out like any http request, with cookies, credentials
+// and a signature over the framed message
+async fn call(
+    request: &RequestMessage,
+    url: String,
+    message: &[u8],
+    session: &Session,
+) -> Result<Response<AsyncBody>, RequestError> {
+    let mut request = request.clone();
+    request.url = url;
+    request.body = RequestBody::Bytes(frame(message));
+    let explicit_cookie = request
+        .headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("Cookie"))
+        .map(|(_, v)| v.clone());
+    http_client::set_cookie_header(&mut request, explicit_cookie.as_deref(), session)?;
+    let auth = http_client::authorize(&mut request, session).await?;
+    let client = http_client::client(&request)?;
+    http_client::send_with_retry(&client, &mut request, auth.as_ref(), session).await
+}
+
+// a server that fails before answering puts the status in the headers
+fn metadata<'a>(response: &'a Response<AsyncBody>, name: &str) -> Option<&'a str> {
+    response
+        .trailer()
+        .try_get()
+        .and_then(|trailers| trailers.get(name))
+        .or_else(|| response.headers().get(name))
+        .and_then(|value| value.to_str().ok())
+}
+
+fn grpc_status(response: &Response<AsyncBody>) -> Option<u32> {
+    metadata(response, "grpc-status").and_then(|status| status.trim().parse().ok())
+}
+
+fn status(response: &Response<AsyncBody>) -> String {
+    let mut status = match grpc_status(response) {
+        Some(code) => format!("grpc-status: {code} {0}", status_name(code)),
+        None => "grpc-status: missing, the server did not end the call".to_owned(),
+    };
+    if let Some(grpc_message) = metadata(response, "grpc-message") {
+        let decoded = percent_encoding::percent_decode_str(grpc_message).decode_utf8_lossy();
+        status.push_str(&format!("\n{decoded}"));
+    }
+    status
+}
+
+// the value has at most 8 digits, longer timeouts move to a coarser unit
+fn grpc_timeout(timeout: Duration) -> String {
+    let millis = timeout.as_millis();
+    [(1, "m"), (1_000, "S"), (60_000, "M"), (3_600_000, "H")]
+        .iter()
+        .map(|(unit, name)| (millis / unit, name))
+        .find(|(value, _)| *value < 100_000_000)
+        .map_or("99999999H".to_owned(), |(value, name)| {
+            format!("{value}{name}")
+        })
+}
+
+// takes the next whole message off the front of the buffer
+fn next_message(buffer: &mut Vec<u8>) -> Result<Option<Vec<u8>>, String> {
+    let Some((prefix, after)) = buffer.split_first_chunk::<5>() else {
+        return Ok(None);
+    };
+    if prefix[0] != 0 {
+        return Err("the server sent a compressed message".to_owned());
+    }
+    let length = u32::from_be_bytes([prefix[1], prefix[2], prefix[3], prefix[4]]) as usize;
+    if after.len() < length {
+        return Ok(None);
+    }
+    let message = after[..length].to_vec();
+    buffer.drain(..5 + length);
+    Ok(Some(message))
+}
+
+fn messages(mut body: Vec<u8>) -> Result<Vec<Vec<u8>>, String> {
+    let mut messages = Vec::new();
+    while let Some(message) = next_message(&mut body)? {
+        messages.push(message);
+    }
+    match body.is_empty() {
+        true => Ok(messages),
+        false => Err("the response ends inside a message".to_owned()),
+    }
+}
+
+fn frame(message: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(message.len() + 5);
+    framed.push(0);
+    framed.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    framed.extend_from_slice(message);
+    framed
+}
+
+fn encode(descriptor: MessageDescriptor, message: Option<&toml::Table>) -> Result<Vec<u8>, String> {
+    let name = descriptor.full_name().to_owned();
+    let table = toml::Value::Table(message.cloned().unwrap_or_default());
+    let message = DynamicMessage::deserialize(descriptor, request_message::toml_to_json(&table))
+        .map_err(|e| format!("does not match {name}: {e}"))?;
+    Ok(message.encode_to_vec())
+}
+
+fn decode(descriptor: MessageDescriptor, bytes: &[u8]) -> String {
+    let name = descriptor.full_name().to_owned();
+    let message = match DynamicMessage::decode(descriptor, bytes) {
+        Ok(message) => message,
+        Err(e) => return format!("could not decode {name}: {e}"),
+    };
+    // fields left at their default value are shown too
+    let options = SerializeOptions::new().skip_default_fields(false);
+    let mut json = Vec::new();
+    let mut serializer = serde_json::Serializer::pretty(&mut json);
+    match message.serialize_with_options(&mut serializer, &options) {
+        Ok(_) => String::from_utf8_lossy(&json).to_string(),
+        Err(e) => format!("could not show {name}: {e}"),
+    }
+}
+
+fn replace_path(url: &str, path: &str) -> String {
+    match Url::parse(url) {
+        Ok(mut url) => {
+            url.set_path(path);
+            url.set_query(None);
+            url.to_string()
+        }
+        Err(_) => url.to_owned(),
+    }
+}
+
+fn status_name(code: u32) -> &'static str {
+    STATUS_NAMES.get(code as usize).copied().unwrap_or("")
+}
+
+impl Display for GrpcCall {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let elapsed = self
+            .finished_in
+            .unwrap_or_else(|| self.started_at.elapsed());
+        let noun = if self.messages == 1 {
+            "message"
+        } else {
+            "messages"
+        };
+        write!(
+            f,
+            "{0} {noun} in {1:.1}s",
+            self.messages,
+            elapsed.as_secs_f64()
+        )?;
+        match &self.status {
+            Some(status) => write!(f, ", {status}"),
+            None => write!(f, ", waiting for more"),
+        }
+    }
+}
+
+impl Display for GrpcConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{0}/{1}", self.service, self.method)?;
+        match &self.proto {
+            Some(proto) => writeln!(f, "from {proto}")?,
+            None => writeln!(f, "from server reflection")?,
+        }
+        if let Some(message) = &self.message {
+            let message = toml::Value::Table(message.clone());
+            write!(
+                f,
+                "{0}",
+                serde_json::to_string_pretty(&request_message::toml_to_json(&message))
+                    .unwrap_or_default()
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_grpc_timeout_to_eight_digits() {
+        let timeout = |millis| grpc_timeout(Duration::from_millis(millis));
+        assert_eq!(timeout(5_000), "5000m");
+        assert_eq!(timeout(99_999_999), "99999999m");
+        assert_eq!(timeout(100_000_000), "100000S");
+        assert_eq!(timeout(u64::MAX / 1_000_000), "5124095H");
+        assert_eq!(grpc_timeout(Duration::MAX), "99999999H");
+    }
+
+    #[test]
+    fn frames_with_flag_and_big_endian_length() {
+        assert_eq!(frame(b"abc"), [0, 0, 0, 0, 3, b'a', b'b', b'c']);
+        assert_eq!(frame(b""), [0, 0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn splits_messages_arriving_in_pieces() {
+        let stream = [frame(b"first"), frame(b""), frame(b"second")].concat();
+        let mut buffer = Vec::new();
+        let mut received = Vec::new();
+        for chunk in stream.chunks(3) {
+            buffer.extend_from_slice(chunk);
+            while let Some(message) = next_message(&mut buffer).unwrap() {
+                received.push(message);
+            }
+        }
+        assert_eq!(
+            received,
+            [b"first".to_vec(), Vec::new(), b"second".to_vec()]
+        );
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn waits_for_the_whole_prefix_and_message() {
+        let mut buffer = vec![0, 0, 0];
+        assert_eq!(next_message(&mut buffer), Ok(None));
+        buffer.extend_from_slice(&[0, 4, b'a']);
+        assert_eq!(next_message(&mut buffer), Ok(None));
+        assert_eq!(buffer.len(), 6);
+    }
+
+    #[test]
+    fn rejects_compressed_and_truncated_messages() {
+        let mut compressed = vec![1, 0, 0, 0, 1, b'a'];
+        assert!(next_message(&mut compressed).is_err());
+        let truncated = [frame(b"whole"), frame(b"cut")[..6].to_vec()].concat();
+        assert_eq!(
+            messages(truncated),
+            Err("the response ends inside a message".to_owned())
+        );
+        assert_eq!(messages(frame(b"one")), Ok(vec![b"one".to_vec()]));
+    }
+}
@@ -1,62 +1,845 @@
+use std::path::Path;
+use std::sync::atomic::AtomicU64;
+use std::sync::Arc;
 use std::time::Instant;
 
+use async_std::task;
+use isahc::config::{Configurable, RedirectPolicy, VersionNegotiation};
+use isahc::error::ErrorKind;
+use isahc::http::{Method, Request, Response, StatusCode, Version};
+use isahc::{AsyncBody, AsyncReadResponseExt, HttpClient, ResponseExt};
+use url::Url;
+
+use crate::auth::Auth;
+use crate::body_stream::BodyStream;
+use crate::certificate::Peer;
+use crate::compression::{self, CompressedBody, CompressedFile, WireSize};
+use crate::digest_auth;
+use crate::event_stream::{self, EventStream};
+use crate::proxy;
+use crate::request_body::RequestBody;
+use crate::request_message::{HttpVerb, HttpVersion, Protocol};
 use crate::response_message::ResponseMessage;
+use crate::session::Session;
 use crate::{request_error::RequestError, request_message::RequestMessage};
 
-pub async fn send(request_message: RequestMessage) -> Result<ResponseMessage, RequestError> {
-    let mut request = match request_message.method {
-        crate::request_message::HttpVerb::GET => surf::get(request_message.url).build(),
-        crate::request_message::HttpVerb::HEAD => surf::head(request_message.url).build(),
-        crate::request_message::HttpVerb::POST => surf::post(request_message.url)
-            .body_string(request_message.body)
-            .build(),
-        crate::request_message::HttpVerb::PUT => surf::put(request_message.url)
-            .body_string(request_message.body)
-            .build(),
-        crate::request_message::HttpVerb::DELETE => surf::delete(request_message.url)
-            .body_string(request_message.body)
-            .build(),
-        crate::request_message::HttpVerb::OPTIONS => surf::options(request_message.url).build(),
-        crate::request_message::HttpVerb::PATCH => surf::patch(request_message.url)
-            .body_string(request_message.body)
-            .build(),
-    };
-
-    for kv in request_message.headers.iter() {
-        let header_key = kv.0.as_str();
-        let header_value = kv.1;
-        request.set_header(header_key, header_value);
-    }
-
-    let client = surf::client();
+pub async fn send(
+    mut request_message: RequestMessage,
+    session: &Session,
+) -> Result<ResponseMessage, RequestError> {
+    let mut explicit_cookie = request_message
+        .headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("Cookie"))
+        .map(|(_, v)| v.clone());
+    set_cookie_header(&mut request_message, explicit_cookie.as_deref(), session)?;
+
+    let compressed_request = compress_body(&mut request_message).await?;
+    let mut auth = authorize(&mut request_message, session).await?;
+    let client = client(&request_message)?;
     let started_at = Instant::now();
 
-    let result = client.send(request).await;
+    let mut redirects = Vec::new();
+    let response = loop {
+        let hop_started_at = Instant::now();
+        let mut response =
+            send_with_retry(&client, &mut request_message, auth.as_ref(), session).await?;
 
-    return match result {
-        Ok(mut response) => {
-            let status = response.status() as u16;
-            let time = started_at.elapsed().as_millis();
-            let body_read = response.body_string().await;
-            let mut headers = Vec::new();
+        if let Some(Auth::Digest(digest)) = &auth {
+            let challenge =
+                digest_auth::find_challenge(header_values(&response, "WWW-Authenticate"));
+            if let (StatusCode::UNAUTHORIZED, Some(challenge)) = (response.status(), challenge) {
+                let authorization = digest.authorization(&request_message, challenge)?;
+                request_message.set_header("Authorization", &authorization);
+                response =
+                    send_with_retry(&client, &mut request_message, auth.as_ref(), session).await?;
+            }
+        }
 
-            for header_name in response.header_names() {
-                let maybe_value = response.header(header_name);
-                if let Some(value) = maybe_value {
-                    headers.push(format!("{}: {}", header_name, value))
+        let location = redirect_location(&request_message, &response)?;
+        match location {
+            Some(location) if request_message.follow_redirects => {
+                if redirects.len() as u32 == request_message.max_redirects {
+                    return Err(RequestError::TooManyRedirects {
+                        url: request_message.url,
+                        max: request_message.max_redirects,
+                    });
+                }
+                let hop_status = response.status();
+                let time = hop_started_at.elapsed().as_millis();
+                redirects.push(read_response(&request_message, response, time).await?);
+                if !follow_redirect(&mut request_message, hop_status, &location) {
+                    // hand written cookies and credentials stay with the origin of the request file
+                    explicit_cookie = None;
+                    auth = None;
                 }
+                set_cookie_header(&mut request_message, explicit_cookie.as_deref(), session)?;
             }
+            _ => break response,
+        }
+    };
 
-            return match body_read {
-                Ok(body) => Ok(ResponseMessage {
-                    status,
-                    time_in_ms: time,
-                    body,
-                    headers
-                }),
-                Err(inner) => Err(RequestError::HttpError { inner }),
-            };
+    let time = started_at.elapsed().as_millis();
+    // the final body is handed over and read while it is displayed
+    let (response, wire) = decode_body(&request_message, response);
+    let mut response_message = response_head(&request_message, &response, time);
+    response_message.compressed_request = compressed_request;
+    let content_type = header_values(&response, "Content-Type")
+        .next()
+        .map(str::to_owned);
+    let address = response.remote_addr();
+    let body = response.into_body();
+    if event_stream::is_event_stream(content_type.as_deref()) {
+        response_message.event_stream = Some(EventStream::new(body));
+    } else {
+        let save_to = request_message.save_to.clone();
+        response_message.body_stream = Some(BodyStream::new(
+            body,
+            content_type.as_deref(),
+            save_to,
+            wire,
+        ));
+    }
+    response_message.redirects = redirects;
+
+    let url = Url::parse(&request_message.url)
+        .ok()
+        .filter(|_| request_message.socket_path.is_none());
+    if let Some(url) = url.filter(|url| url.scheme() == "https") {
+        let proxy = proxy::resolve(request_message.proxy.as_ref(), &url);
+        // through a proxy the address is the one of the proxy
+        let address = address.filter(|_| proxy.is_none());
+        response_message.peer = Some(Peer::new(
+            url,
+            address,
+            request_message.tls.clone(),
+            proxy,
+            request_message.timeout,
+        ));
+    }
+    if let Some(assert) = &request_message.assert {
+        if assert.needs_connection() {
+            if let Some(peer) = response_message.peer.take() {
+                response_message.connection = Some(peer.inspect().await);
+            }
+        }
+        response_message.assertions = assert.check(response_message.connection.as_ref());
+    }
+    Ok(response_message)
+}
+
+// the credentials are handed back for a digest challenge and for signing each attempt
+pub async fn authorize(
+    request_message: &mut RequestMessage,
+    session: &Session,
+) -> Result<Option<Auth>, RequestError> {
+    let auth = request_message.auth.take();
+    if let Some(auth) = &auth {
+        auth.apply(request_message, session).await?;
+    }
+    Ok(auth)
+}
+
+pub async fn sign(
+    request_message: &mut RequestMessage,
+    auth: Option<&Auth>,
+) -> Result<(), RequestError> {
+    if let Some(auth) = auth {
+        auth.sign(request_message).await?;
+    }
+    // signed last, the signature covers the credentials and the final body
+    if let Some(signature) = request_message.signature.take() {
+        let signed = signature.sign(request_message).await;
+        request_message.signature = Some(signature);
+        signed?;
+    }
+    Ok(())
+}
+
+pub fn client(request_message: &RequestMessage) -> Result<HttpClient, RequestError> {
+    let mut client_builder = HttpClient::builder();
+    if let Some(tls) = &request_message.tls {
+        client_builder = tls.configure(client_builder);
+    }
+    client_builder
+        .build()
+        .map_err(|inner| RequestError::HttpError { inner })
+}
+
+async fn read_response(
+    request_message: &RequestMessage,
+    response: Response<AsyncBody>,
+    time_in_ms: u128,
+) -> Result<ResponseMessage, RequestError> {
+    let (mut response, _) = decode_body(request_message, response);
+    let mut response_message = response_head(request_message, &response, time_in_ms);
+    response_message.body = response
+        .text()
+        .await
+        .map_err(|e| to_request_error(request_message, e.into()))?;
+    Ok(response_message)
+}
+
+// curl leaves the body as it was sent so its size on the wire can be counted
+fn decode_body(
+    request_message: &RequestMessage,
+    response: Response<AsyncBody>,
+) -> (Response<AsyncBody>, Option<WireSize>) {
+    // decoders fail on the empty body of a HEAD, 204 or 304 that names an encoding
+    let has_body = !matches!(request_message.method, HttpVerb::HEAD)
+        && !matches!(
+            response.status(),
+            StatusCode::NO_CONTENT | StatusCode::NOT_MODIFIED
+        )
+        && header_values(&response, "Content-Length").next() != Some("0");
+    let content_encoding = header_values(&response, "Content-Encoding")
+        .next()
+        .filter(|_| has_body)
+        .map(str::to_owned);
+    let (parts, body) = response.into_parts();
+    let (body, wire) = compression::decode(
+        body,
+        content_encoding.as_deref(),
+        request_message.compression.as_ref(),
+    );
+    (Response::from_parts(parts, body), wire)
+}
+
+pub fn response_head(
+    request_message: &RequestMessage,
+    response: &Response<AsyncBody>,
+    time_in_ms: u128,
+) -> ResponseMessage {
+    let headers = response
+        .headers()
+        .iter()
+        .map(|(name, value)| format!("{name}: {0}", String::from_utf8_lossy(value.as_bytes())))
+        .collect::<Vec<String>>();
+    // some libcurl builds fall back to 1.1 when ALPN picks it over a pinned HTTP/2
+    let version = version_name(response.version());
+    let version = match request_message.http_version {
+        HttpVersion::Http2 | HttpVersion::H2c if version != "HTTP/2" => {
+            format!("{version}, {0} was asked for", request_message.http_version)
+        }
+        _ => version.to_owned(),
+    };
+
+    ResponseMessage {
+        method: request_message.method.to_string(),
+        url: request_message.url.clone(),
+        status: response.status().as_u16(),
+        version,
+        compressed_request: None,
+        time_in_ms,
+        body: String::new(),
+        headers,
+        redirects: Vec::new(),
+        connection: None,
+        peer: None,
+        assertions: Vec::new(),
+        event_stream: None,
+        body_stream: None,
+        grpc_stream: None,
+    }
+}
+
+// every attempt is signed again, a signature covers the time, the url and the cookies of its hop
+pub async fn send_with_retry(
+    client: &HttpClient,
+    request_message: &mut RequestMessage,
+    auth: Option<&Auth>,
+    session: &Session,
+) -> Result<Response<AsyncBody>, RequestError> {
+    let mut attempt = 0;
+    loop {
+        sign(request_message, auth).await?;
+        let sending = client.send_async(build_request(request_message).await?);
+        // only the response head is waited for, a streamed body takes as long as it needs
+        let result = match request_message.timeout {
+            Some(timeout) => async_std::future::timeout(timeout, sending)
+                .await
+                .unwrap_or_else(|_| Err(ErrorKind::Timeout.into())),
+            None => sending.await,
+        }
+        .map_err(|e| to_request_error(request_message, e));
+        if let Ok(response) = &result {
+            store_cookies(session, &request_message.url, response);
+        }
+
+        let retry = request_message
+            .retry
+            .as_ref()
+            .filter(|retry| attempt < retry.count());
+        let should_retry = match (retry, &result) {
+            (Some(retry), Ok(response)) => retry.retries_status(response.status().as_u16()),
+            (Some(retry), Err(RequestError::Timeout { .. }))
+            | (Some(retry), Err(RequestError::ConnectionRefused { .. })) => {
+                retry.retries_transport_error(&request_message.method)
+            }
+            _ => false,
+        };
+        match retry.filter(|_| should_retry) {
+            Some(retry) => {
+                task::sleep(retry.backoff(attempt)).await;
+                attempt += 1;
+            }
+            None => return result,
+        }
+    }
+}
+
+pub fn to_request_error(request_message: &RequestMessage, inner: isahc::Error) -> RequestError {
+    let url = match &request_message.socket_path {
+        Some(socket_path) => format!("{0} on {1}", request_message.url, socket_path.display()),
+        None => request_message.url.clone(),
+    };
+    match inner.kind() {
+        ErrorKind::Timeout => RequestError::Timeout { url },
+        // a pinned HTTP/2 request fails the handshake of servers that only offer 1.1
+        ErrorKind::ConnectionFailed
+            if request_message.http_version == HttpVersion::Http2
+                && ssl_connect_error(&inner).is_some() =>
+        {
+            RequestError::TlsError {
+                url,
+                message: "the secure connection failed, the server may not offer HTTP/2".to_owned(),
+            }
+        }
+        ErrorKind::ConnectionFailed => match ssl_connect_error(&inner) {
+            Some(error) => RequestError::TlsError {
+                url,
+                message: curl_message(error),
+            },
+            None => RequestError::ConnectionRefused { url },
+        },
+        ErrorKind::BadServerCertificate
+        | ErrorKind::BadClientCertificate
+        | ErrorKind::TlsEngine => RequestError::TlsError {
+            url,
+            message: curl_error(&inner).map_or(inner.to_string(), curl_message),
+        },
+        _ => RequestError::HttpError { inner },
+    }
+}
+
+fn curl_error(inner: &isahc::Error) -> Option<&curl::Error> {
+    std::error::Error::source(inner).and_then(|source| source.downcast_ref::<curl::Error>())
+}
+
+// isahc reports failed handshakes the same way as refused connections
+fn ssl_connect_error(inner: &isahc::Error) -> Option<&curl::Error> {
+    curl_error(inner).filter(|error| error.is_ssl_connect_error())
+}
+
+fn curl_message(error: &curl::Error) -> String {
+    error
+        .extra_description()
+        .unwrap_or(error.description())
+        .to_owned()
+}
+
+fn header_values<'a>(
+    response: &'a Response<AsyncBody>,
+    name: &str,
+) -> impl Iterator<Item = &'a str> {
+    response
+        .headers()
+        .get_all(name)
+        .into_iter()
+        .filter_map(|value| value.to_str().ok())
+}
+
+fn store_cookies(session: &Session, url: &str, response: &Response<AsyncBody>) {
+    if let Ok(url) = Url::parse(url) {
+        session.store_cookies(&url, header_values(response, "Set-Cookie"));
+    }
+}
+
+pub fn set_cookie_header(
+    request_message: &mut RequestMessage,
+    explicit_cookie: Option<&str>,
+    session: &Session,
+) -> Result<(), RequestError> {
+    let url = Url::parse(&request_message.url).map_err(|e| RequestError::InvalidUrl {
+        url: request_message.url.clone(),
+        message: e.to_string(),
+    })?;
+    let cookie_header = match (explicit_cookie, session.cookie_header(&url)) {
+        (Some(explicit), Some(cookies)) => Some(format!("{explicit}; {cookies}")),
+        (explicit, cookies) => cookies.or(explicit.map(str::to_owned)),
+    };
+    match cookie_header {
+        Some(cookie_header) => request_message.set_header("Cookie", &cookie_header),
+        None => request_message.remove_header("Cookie"),
+    }
+    Ok(())
+}
+
+fn redirect_location(
+    request_message: &RequestMessage,
+    response: &Response<AsyncBody>,
+) -> Result<Option<Url>, RequestError> {
+    if !response.status().is_redirection() {
+        return Ok(None);
+    }
+    let Some(location) = header_values(response, "Location").next() else {
+        return Ok(None);
+    };
+    Url::parse(&request_message.url)
+        .and_then(|url| url.join(location))
+        .map(Some)
+        .map_err(|e| RequestError::InvalidUrl {
+            url: location.to_owned(),
+            message: e.to_string(),
+        })
+}
+
+// returns whether the redirect stays on the same origin
+fn follow_redirect(
+    request_message: &mut RequestMessage,
+    status: StatusCode,
+    location: &Url,
+) -> bool {
+    // like browsers, a 303 and a 301 or 302 answering a POST are followed with a GET
+    let becomes_get = match status {
+        StatusCode::SEE_OTHER => !matches!(request_message.method, HttpVerb::HEAD),
+        StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND => {
+            matches!(request_message.method, HttpVerb::POST)
+        }
+        _ => false,
+    };
+    if becomes_get {
+        request_message.method = HttpVerb::GET;
+        request_message.body = RequestBody::Text(String::new());
+        request_message.remove_header("Content-Type");
+        request_message.remove_header("Content-Length");
+        request_message.remove_header("Content-Encoding");
+    }
+
+    // credentials are not sent to another origin
+    let same_origin =
+        Url::parse(&request_message.url).is_ok_and(|url| url.origin() == location.origin());
+    if !same_origin {
+        request_message.remove_header("Authorization");
+        request_message.remove_header("X-Amz-Security-Token");
+        if let Some(signature) = request_message.signature.take() {
+            for header in signature.headers() {
+                request_message.remove_header(header);
+            }
         }
-        Err(inner) => Err(RequestError::HttpError { inner }),
+        request_message.auth_query.clear();
+        request_message.socket_path = None;
+    }
+    request_message.url = location.to_string();
+    same_origin
+}
+
+async fn build_request(
+    request_message: &RequestMessage,
+) -> Result<Request<AsyncBody>, RequestError> {
+    let method =
+        Method::from_bytes(request_message.method.to_string().as_bytes()).map_err(|e| {
+            RequestError::InvalidValue {
+                property_name: "method".to_owned(),
+                message: e.to_string(),
+            }
+        })?;
+    let mut builder = Request::builder()
+        .method(method)
+        .uri(request_message.wire_url().as_str())
+        .redirect_policy(RedirectPolicy::None)
+        .automatic_decompression(false);
+    builder = match request_message.http_version {
+        HttpVersion::Negotiated => builder,
+        HttpVersion::Http11 => builder.version_negotiation(VersionNegotiation::http11()),
+        // curl offers only h2 through ALPN for https and uses prior knowledge for plain http
+        HttpVersion::Http2 | HttpVersion::H2c => {
+            builder.version_negotiation(VersionNegotiation::http2())
+        }
+    };
+    if let Some(timeout) = request_message.timeout {
+        builder = builder.connect_timeout(timeout);
+    }
+    let url = Url::parse(&request_message.url).map_err(|e| RequestError::InvalidUrl {
+        url: request_message.url.clone(),
+        message: e.to_string(),
+    })?;
+    builder = match (
+        &request_message.socket_path,
+        proxy::resolve(request_message.proxy.as_ref(), &url),
+    ) {
+        (Some(socket_path), _) => dial_socket(builder, socket_path)?,
+        (None, Some(proxy)) => proxy.configure(builder),
+        (None, None) => builder.proxy(None),
+    };
+    for (name, value) in request_message.headers.iter() {
+        builder = builder.header(name.as_str(), value.as_str());
+    }
+    // grpc messages carry their own compression flag
+    let accept_encoding = compression::accept_encoding(request_message.compression.as_ref())
+        .filter(|_| request_message.protocol == Protocol::Http)
+        .filter(|_| !has_header(request_message, "Accept-Encoding"));
+    if let Some(accept_encoding) = accept_encoding {
+        builder = builder.header("Accept-Encoding", accept_encoding);
+    }
+
+    let body = match sends_body(request_message) {
+        false => AsyncBody::empty(),
+        true => {
+            // without a content type curl would announce every body as a form
+            if !has_header(request_message, "Content-Type") {
+                builder = builder.header("Content-Type", content_type(&request_message.body));
+            }
+            to_body(&request_message.body).await?
+        }
+    };
+    builder
+        .body(body)
+        .map_err(|e| RequestError::HttpError { inner: e.into() })
+}
+
+fn version_name(version: Version) -> &'static str {
+    match version {
+        Version::HTTP_09 => "HTTP/0.9",
+        Version::HTTP_10 => "HTTP/1.0",
+        Version::HTTP_11 => "HTTP/1.1",
+        Version::HTTP_2 => "HTTP/2",
+        Version::HTTP_3 => "HTTP/3",
+        _ => "HTTP",
+    }
+}
+
+fn sends_body(request_message: &RequestMessage) -> bool {
+    match &request_message.method {
+        HttpVerb::GET | HttpVerb::HEAD | HttpVerb::OPTIONS => false,
+        HttpVerb::Custom(_) => {
+            !matches!(&request_message.body, RequestBody::Text(t) if t.is_empty())
+        }
+        _ => true,
+    }
+}
+
+fn has_header(request_message: &RequestMessage, name: &str) -> bool {
+    request_message
+        .headers
+        .keys()
+        .any(|k| k.eq_ignore_ascii_case(name))
+}
+
+// the body is compressed before auth and signatures so they cover the bytes sent
+async fn compress_body(
+    request_message: &mut RequestMessage,
+) -> Result<Option<CompressedBody>, RequestError> {
+    let encoding = request_message
+        .compression
+        .as_ref()
+        .and_then(|c| c.request())
+        .map(str::to_owned);
+    let Some(encoding) = encoding.filter(|_| sends_body(request_message)) else {
+        return Ok(None);
+    };
+    let invalid = |message: String| RequestError::InvalidValue {
+        property_name: "compression.request".to_owned(),
+        message,
     };
+    if has_header(request_message, "Content-Encoding") {
+        return Err(invalid(
+            "the request already sets a Content-Encoding header".to_owned(),
+        ));
+    }
+    let (original, body, compressed) = match &request_message.body {
+        RequestBody::Text(text) if text.is_empty() => return Ok(None),
+        RequestBody::Bytes(bytes) if bytes.is_empty() => return Ok(None),
+        RequestBody::Text(text) => compress_bytes(text.as_bytes(), &encoding)
+            .await
+            .map_err(invalid)?,
+        RequestBody::Bytes(bytes) => compress_bytes(bytes, &encoding).await.map_err(invalid)?,
+        // a file is compressed while it is sent instead of read into memory
+        RequestBody::File(path) => {
+            let original = async_std::fs::metadata(path)
+                .await
+                .map_err(|e| RequestError::InvalidValue {
+                    property_name: "body_file".to_owned(),
+                    message: format!("could not read {0}: {e}", path.display()),
+                })?
+                .len();
+            if original == 0 {
+                return Ok(None);
+            }
+            let file = CompressedFile::new(path.clone(), &encoding);
+            let sent = file.sent();
+            (original, RequestBody::CompressedFile(file), sent)
+        }
+        RequestBody::CompressedFile(_) => return Ok(None),
+    };
+
+    // the content type still describes the original body
+    if !has_header(request_message, "Content-Type") {
+        let content_type = content_type(&request_message.body);
+        request_message.set_header("Content-Type", &content_type);
+    }
+    request_message.set_header("Content-Encoding", &encoding);
+    request_message.body = body;
+    Ok(Some(CompressedBody {
+        encoding,
+        original,
+        compressed,
+    }))
+}
+
+async fn compress_bytes(
+    bytes: &[u8],
+    encoding: &str,
+) -> Result<(u64, RequestBody, Arc<AtomicU64>), String> {
+    let compressed = compression::compress(bytes, encoding)
+        .await
+        .map_err(|e| e.to_string())?;
+    let sent = Arc::new(AtomicU64::new(compressed.len() as u64));
+    Ok((bytes.len() as u64, RequestBody::Bytes(compressed), sent))
+}
+
+fn content_type(body: &RequestBody) -> String {
+    match body {
+        RequestBody::Text(_) => "text/plain;charset=utf-8".to_owned(),
+        RequestBody::Bytes(_) => "application/octet-stream".to_owned(),
+        RequestBody::File(path) => mime_guess::from_path(path)
+            .first_or_octet_stream()
+            .to_string(),
+        RequestBody::CompressedFile(file) => mime_guess::from_path(file.path())
+            .first_or_octet_stream()
+            .to_string(),
+    }
+}
+
+#[cfg(unix)]
+fn dial_socket(
+    builder: isahc::http::request::Builder,
+    socket_path: &Path,
+) -> Result<isahc::http::request::Builder, RequestError> {
+    Ok(builder
+        .proxy(None)
+        .dial(isahc::config::Dialer::unix_socket(socket_path)))
+}
+
+#[cfg(not(unix))]
+fn dial_socket(
+    _builder: isahc::http::request::Builder,
+    socket_path: &Path,
+) -> Result<isahc::http::request::Builder, RequestError> {
+    Err(RequestError::InvalidValue {
+        property_name: "socket_path".to_owned(),
+        message: format!(
+            "{0}: unix sockets are not supported on this platform",
+            socket_path.display()
+        ),
+    })
+}
+
+async fn to_body(body: &RequestBody) -> Result<AsyncBody, RequestError> {
+    match body {
+        RequestBody::Text(text) => Ok(AsyncBody::from(text.clone())),
+        RequestBody::Bytes(bytes) => Ok(AsyncBody::from(bytes.clone())),
+        RequestBody::File(path) => {
+            let read_error = |e: std::io::Error| RequestError::InvalidValue {
+                property_name: "body_file".to_owned(),
+                message: format!("could not read {0}: {e}", path.display()),
+            };
+            let file = async_std::fs::File::open(path).await.map_err(read_error)?;
+            let length = file.metadata().await.map_err(read_error)?.len();
+            Ok(AsyncBody::from_reader_sized(file, length))
+        }
+        // the compressed length is not known up front, curl sends it chunked
+        RequestBody::CompressedFile(file) => {
+            let reader = file.open().await.map_err(|e| RequestError::InvalidValue {
+                property_name: "body_file".to_owned(),
+                message: format!("could not read {0}: {e}", file.path().display()),
+            })?;
+            Ok(AsyncBody::from_reader(reader))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use async_std::io::ReadExt as _;
+
+    use super::*;
+
+    // answers each connection with the next response, the last one is repeated;
+    // an empty response keeps the connection open without answering
+    fn serve(responses: &[&'static str]) -> (u16, Arc<AtomicUsize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let responses = responses.to_vec();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let index = counter.fetch_add(1, Ordering::SeqCst);
+                let response = responses[index.min(responses.len() - 1)];
+                let mut head = Vec::new();
+                let mut byte = [0u8; 1];
+                while !head.ends_with(b"\r\n\r\n") && stream.read(&mut byte).unwrap_or(0) == 1 {
+                    head.push(byte[0]);
+                }
+                // a chunked body is read to its end before answering
+                let chunked = String::from_utf8_lossy(&head)
+                    .to_ascii_lowercase()
+                    .contains("transfer-encoding: chunked");
+                let mut body = Vec::new();
+                while chunked
+                    && !body.ends_with(b"0\r\n\r\n")
+                    && stream.read(&mut byte).unwrap_or(0) == 1
+                {
+                    body.push(byte[0]);
+                }
+                if response.is_empty() {
+                    thread::spawn(move || {
+                        thread::sleep(Duration::from_secs(5));
+                        drop(stream);
+                    });
+                } else {
+                    let _ = stream.write_all(response.as_bytes());
+                }
+            }
+        });
+        (port, requests)
+    }
+
+    const OK: &str = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\nok";
+    const UNAVAILABLE: &str =
+        "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const REDIRECT: &str =
+        "HTTP/1.1 302 Found\r\nLocation: /again\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+    const SILENT: &str = "";
+
+    fn send_to(port: u16, settings: &str) -> Result<ResponseMessage, RequestError> {
+        let text = format!("host = \"http://127.0.0.1:{port}\"\npath = \"/\"\n{settings}");
+        let request_message = RequestMessage::from_text(&text)?.to_message()?;
+        task::block_on(send(request_message, &Session::default()))
+    }
+
+    #[test]
+    fn retries_listed_statuses() {
+        let (port, requests) = serve(&[UNAVAILABLE, UNAVAILABLE, OK]);
+        let response = send_to(
+            port,
+            "method = \"POST\"\n[retry]\ncount = 2\nbackoff_ms = 1",
+        )
+        .unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn compresses_a_body_file_again_for_each_attempt() {
+        let path = std::env::temp_dir().join(format!("ghucl-body-{0}.json", std::process::id()));
+        let content = "{\"items\": [1, 2, 3]}\n".repeat(1000);
+        std::fs::write(&path, &content).unwrap();
+        let (port, requests) = serve(&[UNAVAILABLE, OK]);
+        let response = send_to(
+            port,
+            &format!(
+                "method = \"POST\"\nbody_file = {0:?}\n[compression]\nrequest = \"gzip\"\n[retry]\ncount = 1\nbackoff_ms = 1",
+                path.display().to_string()
+            ),
+        );
+        // the count restarts with every attempt, it ends at the size of one compressed body
+        let mut compressed = Vec::new();
+        task::block_on(async {
+            let mut reader = CompressedFile::new(path.clone(), "gzip").open().await?;
+            reader.read_to_end(&mut compressed).await
+        })
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let response = response.unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert_eq!(
+            response.compressed_request.unwrap().to_string(),
+            format!(
+                "{0} B sent as {1} B with gzip",
+                content.len(),
+                compressed.len()
+            )
+        );
+    }
+
+    #[test]
+    fn gives_up_after_the_retry_count() {
+        let (port, requests) = serve(&[UNAVAILABLE]);
+        let response =
+            send_to(port, "method = \"GET\"\n[retry]\ncount = 1\nbackoff_ms = 1").unwrap();
+        assert_eq!(response.status, 503);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn reports_timeouts_and_retries_them_for_idempotent_methods() {
+        let settings = "timeout_ms = 200\n[retry]\ncount = 1\nbackoff_ms = 1";
+        let (port, requests) = serve(&[SILENT]);
+        let error = send_to(port, &format!("method = \"GET\"\n{settings}"))
+            .err()
+            .unwrap();
+        assert!(matches!(error, RequestError::Timeout { .. }));
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+
+        let (port, requests) = serve(&[SILENT]);
+        let error = send_to(port, &format!("method = \"POST\"\n{settings}"))
+            .err()
+            .unwrap();
+        assert!(matches!(error, RequestError::Timeout { .. }));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn timeout_does_not_limit_the_body() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let _ = stream.read(&mut [0u8; 1024]);
+            let head = "HTTP/1.1 200 OK\r\nContent-Length: 4\r\nConnection: close\r\n\r\nab";
+            stream.write_all(head.as_bytes()).unwrap();
+            thread::sleep(Duration::from_millis(400));
+            stream.write_all(b"cd").unwrap();
+        });
+        let mut response = send_to(port, "method = \"GET\"\ntimeout_ms = 200").unwrap();
+        let stream = response.body_stream.take().unwrap();
+        assert_eq!(task::block_on(stream.read_to_string()).unwrap(), "abcd");
+    }
+
+    #[test]
+    fn reports_refused_connections() {
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+        let error = send_to(port, "method = \"GET\"").err().unwrap();
+        assert!(matches!(error, RequestError::ConnectionRefused { .. }));
+    }
+
+    #[test]
+    fn stops_following_after_max_redirects() {
+        let (port, requests) = serve(&[REDIRECT]);
+        let error = send_to(
+            port,
+            "method = \"GET\"\nfollow_redirects = true\nmax_redirects = 2",
+        )
+        .err()
+        .unwrap();
+        assert!(matches!(
+            error,
+            RequestError::TooManyRedirects { max: 2, .. }
+        ));
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
 }
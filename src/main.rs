@@ -1,22 +1,52 @@
+mod assertion;
+mod auth;
+mod aws_sigv4;
+mod body_stream;
+mod certificate;
+mod compression;
+mod cookie_jar;
+mod digest_auth;
+mod event_stream;
 mod file_tree;
+mod graphql;
+mod grpc;
 mod http_client;
 mod message;
+mod oauth2;
+mod proxy;
+mod request_body;
 mod request_error;
 mod request_message;
 mod response_message;
+mod retry;
+mod session;
+mod signature;
+mod tls;
+mod websocket;
 
 use std::fs;
 use std::path::Path;
 
 use async_std::task;
+use auth::Auth;
+use body_stream::Transfer;
+use event_stream::EventLog;
 use file_tree::FileTree;
+use graphql::SchemaCache;
+use grpc::GrpcCall;
 use iced::widget::{
-    button, column, horizontal_space, pick_list, row, scrollable, text, text_input,
+    button, checkbox, column, horizontal_space, pick_list, row, scrollable, text, text_input,
+};
+use iced::{
+    executor, theme, Alignment, Application, Color, Command, Element, Length, Settings,
+    Subscription, Theme,
 };
-use iced::{Alignment, Element, Length, Sandbox, Settings};
 use message::Message;
 use request_error::RequestError;
-use request_message::RequestMessageBuilder;
+use request_message::{Protocol, RequestMessage, RequestMessageBuilder};
+use response_message::ResponseMessage;
+use session::Session;
+use websocket::WebSocketSession;
 
 pub fn main() -> iced::Result {
     Root::run(Settings::default())
@@ -30,38 +60,71 @@ struct Root {
     file_content: String,
     file_tree: Option<FileTree>,
     reponse: String,
+    last_response: Option<ResponseMessage>,
+    selected_hop: usize,
+    show_connection: bool,
+    inspecting: bool,
     base_builder: Option<request_message::RequestMessageBuilder>,
     req_builder: Option<request_message::RequestMessageBuilder>,
     req_content: String,
+    graphql_message: Option<RequestMessage>,
+    session: Session,
+    show_cookies: bool,
+    events: Option<EventLog>,
+    reconnect_events: bool,
+    transfer: Option<Transfer>,
+    grpc: Option<GrpcCall>,
+    websocket: Option<WebSocketSession>,
+    schemas: SchemaCache,
+    schema_path: String,
 }
 
-impl Sandbox for Root {
+impl Application for Root {
+    type Executor = executor::Default;
     type Message = Message;
+    type Theme = Theme;
+    type Flags = ();
 
-    fn new() -> Self {
-        Self {
+    fn new(_flags: ()) -> (Self, Command<Message>) {
+        let root = Self {
             files: vec![],
             current_base: None,
             folder_path: String::from(""),
             file_tree: None,
             file_content: String::from("no file selected"),
             reponse: String::from("empty for now"),
+            last_response: None,
+            selected_hop: 0,
+            show_connection: false,
+            inspecting: false,
             base_builder: None,
             req_builder: None,
             req_content: String::from("[none]"),
-        }
+            graphql_message: None,
+            session: Session::load(),
+            show_cookies: false,
+            events: None,
+            reconnect_events: false,
+            transfer: None,
+            grpc: None,
+            websocket: None,
+            schemas: SchemaCache::load(),
+            schema_path: String::new(),
+        };
+        (root, Command::none())
     }
 
     fn title(&self) -> String {
         String::from("Ghucl")
     }
 
-    fn update(&mut self, message: Message) {
+    fn update(&mut self, message: Message) -> Command<Message> {
         match message {
             Message::BaseFileChanged(file_name) => {
                 self.current_base = Some(file_name.clone());
                 let base_path = Path::new(&self.folder_path).join(&file_name);
                 let full_path = base_path.to_str().unwrap_or("");
+                self.session.set_environment(full_path);
 
                 self.base_builder = match Root::get_builder_from_file(full_path) {
                     Ok(rmb) => Some(rmb),
@@ -70,13 +133,13 @@ impl Sandbox for Root {
                         None
                     }
                 };
+                self.build_graphql_message();
             }
             Message::FolderChanged => {
                 println!("folder changed {0}", self.folder_path);
-                let result = FileTree::from_path(&self.folder_path).and_then(|tree| {
+                let result = FileTree::from_path(&self.folder_path).map(|tree| {
                     self.files = tree.get_file_names();
                     self.file_tree = Some(tree);
-                    Ok(())
                 });
                 match result {
                     Ok(_) => {}
@@ -96,9 +159,11 @@ impl Sandbox for Root {
             Message::FileSelected(file_path) => {
                 match fs::read_to_string(&file_path) {
                     Ok(content) => {
+                        let file_dir = Path::new(&file_path).parent().unwrap_or(Path::new(""));
                         self.req_builder =
                             match request_message::RequestMessage::from_text(&content) {
                                 Ok(rmb) => {
+                                    let rmb = rmb.relative_to(file_dir);
                                     if let Some(base_buiilder) = &self.base_builder {
                                         let req = base_buiilder.merge_with(&rmb);
                                         self.req_content = format!("{req}");
@@ -114,26 +179,215 @@ impl Sandbox for Root {
                                 }
                             };
                         self.file_content = content;
+                        self.build_graphql_message();
                     }
                     Err(_) => self.notify("Could not read file"),
                 };
             }
             Message::Send => match &self.req_builder {
                 Some(req_builder) => match req_builder.to_message() {
-                    Ok(message) => {
-                        match task::block_on(http_client::send(message)) {
-                            Ok(respone) => self.reponse = format!("{0}", respone),
-                            Err(_) => self.notify("error while sending request"),
-                        };
+                    Ok(message) if message.protocol == Protocol::WebSocket => {
+                        self.clear_result();
+                        self.websocket = Some(WebSocketSession::new(message));
                     }
-                    Err(_) => self.notify("error while sending request"),
+                    Ok(message) if message.protocol == Protocol::Grpc => {
+                        match task::block_on(grpc::send(message, &self.session)) {
+                            Ok(mut respone) => {
+                                self.clear_result();
+                                self.reponse = format!("{0}", respone);
+                                self.selected_hop = 0;
+                                self.grpc = respone.grpc_stream.take().map(GrpcCall::new);
+                                self.last_response = Some(respone);
+                            }
+                            Err(e) => self.notify(format!("could not send request: {e}").as_str()),
+                        }
+                    }
+                    Ok(message) => match self.graphql_errors(&message) {
+                        errors if !errors.is_empty() => self.notify(
+                            format!("the query was not sent:\n{0}", errors.join("\n")).as_str(),
+                        ),
+                        _ => {
+                            self.websocket = None;
+                            self.grpc = None;
+                            match task::block_on(http_client::send(message, &self.session)) {
+                                Ok(mut respone) => {
+                                    self.reponse = format!("{0}", respone);
+                                    self.selected_hop = respone.redirects.len();
+                                    self.events = respone.event_stream.take().map(|stream| {
+                                        EventLog::new(stream, &respone.url, req_builder.clone())
+                                    });
+                                    self.transfer = respone.body_stream.take().map(|stream| {
+                                        Transfer::new(stream, respone.content_length())
+                                    });
+                                    self.last_response = Some(respone);
+                                    self.inspecting = false;
+                                    if self.show_connection {
+                                        return self.inspect_connection();
+                                    }
+                                }
+                                Err(e) => {
+                                    self.notify(format!("could not send request: {e}").as_str())
+                                }
+                            };
+                        }
+                    },
+                    Err(e) => self.notify(format!("could not build request: {e}").as_str()),
                 },
                 None => self.notify("Could not send the message"),
             },
+            Message::HopSelected(index) => {
+                let hop = self
+                    .last_response
+                    .as_ref()
+                    .and_then(|r| r.hops().get(index).map(|hop| format!("{hop}")));
+                if let Some(hop) = hop {
+                    self.reponse = hop;
+                    self.selected_hop = index;
+                }
+            }
+            Message::ShowConnection(show) => {
+                self.show_connection = show;
+                if show {
+                    return self.inspect_connection();
+                }
+            }
+            Message::ConnectionInspected(connection) => {
+                if let Some(response) = self.last_response.as_mut().filter(|_| self.inspecting) {
+                    response.connection = Some(connection);
+                }
+                self.inspecting = false;
+            }
+            Message::EventStreamUpdate(update) => {
+                if let Some(events) = self.events.as_mut() {
+                    events.update(update, self.reconnect_events);
+                }
+            }
+            Message::TransferUpdate(update) => {
+                if let (Some(transfer), Some(response)) =
+                    (self.transfer.as_mut(), self.last_response.as_mut())
+                {
+                    transfer.update(update, &mut response.body);
+                    if self.selected_hop == response.redirects.len() {
+                        self.reponse = format!("{response}");
+                    }
+                }
+            }
+            Message::StopTransfer => {
+                if let Some(transfer) = self.transfer.as_mut() {
+                    transfer.stop();
+                }
+            }
+            Message::GrpcUpdate(update) => {
+                if let (Some(grpc), Some(response)) =
+                    (self.grpc.as_mut(), self.last_response.as_mut())
+                {
+                    grpc.update(update, response);
+                    self.reponse = format!("{response}");
+                }
+            }
+            Message::StopGrpc => {
+                if let (Some(grpc), Some(response)) =
+                    (self.grpc.as_mut(), self.last_response.as_mut())
+                {
+                    grpc.stop(&mut response.body);
+                    self.reponse = format!("{response}");
+                }
+            }
+            Message::WebSocketUpdate(update) => {
+                if let Some(websocket) = self.websocket.as_mut() {
+                    websocket.update(update);
+                }
+            }
+            Message::WebSocketDraftChanged(draft) => {
+                if let Some(websocket) = self.websocket.as_mut() {
+                    websocket.draft = draft;
+                }
+            }
+            Message::WebSocketBinaryToggled(binary) => {
+                if let Some(websocket) = self.websocket.as_mut() {
+                    websocket.binary = binary;
+                }
+            }
+            Message::WebSocketSend => {
+                if let Some(websocket) = self.websocket.as_mut() {
+                    websocket.send_draft();
+                }
+            }
+            Message::WebSocketClose => {
+                if let Some(websocket) = self.websocket.as_mut() {
+                    websocket.close();
+                }
+            }
+            Message::StopEvents => {
+                if let Some(events) = self.events.as_mut() {
+                    events.stop("stopped".to_owned());
+                }
+            }
+            Message::ClearEvents => {
+                if let Some(events) = self.events.as_mut() {
+                    events.events.clear();
+                }
+            }
+            Message::ReconnectEventsToggled(reconnect) => self.reconnect_events = reconnect,
+            Message::ToggleCookies => self.show_cookies = !self.show_cookies,
+            Message::CookieValueChanged(index, value) => {
+                self.session.set_cookie_value(index, value)
+            }
+            Message::CookieRemoved(index) => self.session.remove_cookie(index),
+            Message::PersistCookiesToggled(persist) => self.session.set_persist_cookies(persist),
+            Message::IntrospectSchema => match self.req_builder.as_ref().map(|b| b.to_message()) {
+                Some(Ok(message)) => {
+                    let endpoint = message.url.clone();
+                    match task::block_on(graphql::introspect(message, &self.session)) {
+                        Ok(schema) => self.schemas.insert(&endpoint, schema),
+                        Err(e) => self.notify(format!("could not load the schema: {e}").as_str()),
+                    }
+                }
+                Some(Err(e)) => self.notify(format!("could not build request: {e}").as_str()),
+                None => {}
+            },
+            Message::SchemaPathChanged(path) => self.schema_path = path,
+            Message::RefreshToken => {
+                if let Some(req_builder) = &self.req_builder {
+                    if let Some(Auth::OAuth2(config)) = req_builder.auth() {
+                        let refreshed = req_builder.to_message().and_then(|message| {
+                            task::block_on(config.refresh(&message, &self.session))
+                        });
+                        if let Err(e) = refreshed {
+                            self.notify(format!("{e}").as_str());
+                        }
+                    }
+                }
+            }
         }
+        Command::none()
+    }
+
+    fn subscription(&self) -> Subscription<Message> {
+        let events = match &self.events {
+            Some(events) => events
+                .subscription(&self.session)
+                .map(Message::EventStreamUpdate),
+            None => Subscription::none(),
+        };
+        let transfer = match &self.transfer {
+            Some(transfer) => transfer.subscription().map(Message::TransferUpdate),
+            None => Subscription::none(),
+        };
+        let grpc = match &self.grpc {
+            Some(grpc) => grpc.subscription().map(Message::GrpcUpdate),
+            None => Subscription::none(),
+        };
+        let websocket = match &self.websocket {
+            Some(websocket) => websocket
+                .subscription(&self.session)
+                .map(Message::WebSocketUpdate),
+            None => Subscription::none(),
+        };
+        Subscription::batch([events, transfer, grpc, websocket])
     }
 
-    fn view(&self) -> Element<Message> {
+    fn view(&self) -> Element<'_, Message> {
         let folder_component = row![
             text("folder path:"),
             text_input("folder path", &self.folder_path)
@@ -154,6 +408,12 @@ impl Sandbox for Root {
             )
             .placeholder("choose a file"),
             horizontal_space(),
+            button(if self.show_cookies {
+                "response"
+            } else {
+                "cookies"
+            })
+            .on_press(Message::ToggleCookies),
             button("send").on_press(Message::Send)
         ]
         .padding(10)
@@ -166,16 +426,50 @@ impl Sandbox for Root {
         })
         .width(Length::FillPortion(1));
 
-        let request_view =
-            scrollable(column![text(&self.file_content), text(&self.req_content),].spacing(10))
-                .width(Length::FillPortion(1))
-                .height(Length::Fill)
-                .direction(scrollable::Direction::Both {
-                    vertical: scrollable::Properties::default(),
-                    horizontal: scrollable::Properties::default(),
-                });
+        let token_status: Element<Message> = match self.req_builder.as_ref().and_then(|b| b.auth())
+        {
+            Some(Auth::OAuth2(config)) => row![
+                text(format!("token: {0}", config.status(&self.session))),
+                button("refresh token").on_press(Message::RefreshToken),
+            ]
+            .align_items(Alignment::Center)
+            .spacing(10)
+            .into(),
+            _ => column![].into(),
+        };
+
+        let request_view = scrollable(
+            column![
+                text(&self.file_content),
+                token_status,
+                self.graphql_elements(),
+                text(&self.req_content),
+            ]
+            .spacing(10),
+        )
+        .width(Length::FillPortion(1))
+        .height(Length::Fill)
+        .direction(scrollable::Direction::Both {
+            vertical: scrollable::Properties::default(),
+            horizontal: scrollable::Properties::default(),
+        });
 
-        let result_view = scrollable(text(&self.reponse))
+        let result_content: Element<Message> = if self.show_cookies {
+            self.cookie_elements()
+        } else if self.websocket.is_some() {
+            self.websocket_elements()
+        } else {
+            column![
+                self.redirect_chain(),
+                self.transfer_elements(),
+                self.grpc_elements(),
+                self.event_elements(),
+                self.response_tabs()
+            ]
+            .spacing(10)
+            .into()
+        };
+        let result_view = scrollable(result_content)
             .width(Length::FillPortion(1))
             .height(Length::Fill)
             .direction(scrollable::Direction::Both {
@@ -183,8 +477,23 @@ impl Sandbox for Root {
                 horizontal: scrollable::Properties::default(),
             });
 
+        let insecure = self
+            .req_builder
+            .as_ref()
+            .or(self.base_builder.as_ref())
+            .and_then(|b| b.tls())
+            .is_some_and(|tls| tls.insecure());
+        let tls_warning: Element<Message> = if insecure {
+            text("WARNING: TLS certificate verification is disabled, the server identity is not checked")
+                .size(20)
+                .style(Color::from_rgb(0.85, 0.1, 0.1))
+                .into()
+        } else {
+            column![].into()
+        };
+
         let content_row = row![tree_view, request_view, result_view].spacing(20);
-        column![folder_component, header, content_row,]
+        column![folder_component, header, tls_warning, content_row,]
             .padding(20)
             .align_items(Alignment::Center)
             .into()
@@ -192,11 +501,267 @@ impl Sandbox for Root {
 }
 
 impl Root {
+    fn cookie_elements(&self) -> Element<'_, Message> {
+        let persist = checkbox("keep cookies after closing", self.session.persist_cookies())
+            .on_toggle(Message::PersistCookiesToggled);
+        let cookies: Vec<Element<Message>> = self
+            .session
+            .cookies()
+            .into_iter()
+            .enumerate()
+            .map(|(index, cookie)| {
+                column![
+                    text(format!("{cookie}")),
+                    row![
+                        text(format!("{0} =", cookie.name)),
+                        text_input("value", &cookie.value)
+                            .on_input(move |value| Message::CookieValueChanged(index, value))
+                            .width(Length::Fixed(300.0)),
+                        button("delete").on_press(Message::CookieRemoved(index)),
+                    ]
+                    .align_items(Alignment::Center)
+                    .spacing(10),
+                ]
+                .spacing(5)
+                .into()
+            })
+            .collect();
+
+        let cookie_list: Element<Message> = if cookies.is_empty() {
+            text("no cookies").into()
+        } else {
+            column(cookies).spacing(15).into()
+        };
+        column![persist, cookie_list].spacing(20).into()
+    }
+
+    fn websocket_elements(&self) -> Element<'_, Message> {
+        let Some(websocket) = &self.websocket else {
+            return column![].into();
+        };
+        let running = |message: Message| websocket.running.then_some(message);
+        let composer = row![
+            text_input("message", &websocket.draft)
+                .on_input(Message::WebSocketDraftChanged)
+                .on_submit(Message::WebSocketSend),
+            checkbox("hex", websocket.binary).on_toggle(Message::WebSocketBinaryToggled),
+            button("send").on_press_maybe(running(Message::WebSocketSend)),
+            button("close").on_press_maybe(running(Message::WebSocketClose)),
+        ]
+        .align_items(Alignment::Center)
+        .spacing(10);
+        let messages: Vec<Element<Message>> = websocket
+            .messages
+            .iter()
+            .map(|message| text(format!("{message}")).into())
+            .collect();
+        column![
+            text(&websocket.status),
+            composer,
+            column(messages).spacing(10)
+        ]
+        .spacing(10)
+        .into()
+    }
+
+    fn transfer_elements(&self) -> Element<'_, Message> {
+        let Some(transfer) = &self.transfer else {
+            return column![].into();
+        };
+        let stop = button("stop").on_press_maybe(transfer.running.then_some(Message::StopTransfer));
+        row![text(format!("{transfer}")), stop]
+            .align_items(Alignment::Center)
+            .spacing(10)
+            .into()
+    }
+
+    fn grpc_elements(&self) -> Element<'_, Message> {
+        let Some(grpc) = &self.grpc else {
+            return column![].into();
+        };
+        let stop = button("stop").on_press_maybe(grpc.running.then_some(Message::StopGrpc));
+        row![text(format!("{grpc}")), stop]
+            .align_items(Alignment::Center)
+            .spacing(10)
+            .into()
+    }
+
+    fn event_elements(&self) -> Element<'_, Message> {
+        let Some(events) = &self.events else {
+            return column![].into();
+        };
+        let stop = button("stop").on_press_maybe(events.running.then_some(Message::StopEvents));
+        let controls = row![
+            stop,
+            button("clear").on_press(Message::ClearEvents),
+            checkbox("reconnect", self.reconnect_events).on_toggle(Message::ReconnectEventsToggled),
+        ]
+        .align_items(Alignment::Center)
+        .spacing(10);
+        let received: Vec<Element<Message>> = events
+            .events
+            .iter()
+            .map(|event| text(format!("{event}")).into())
+            .collect();
+        column![
+            text(format!(
+                "{0} events, {1}",
+                events.events.len(),
+                events.status
+            )),
+            controls,
+            column(received).spacing(10),
+        ]
+        .spacing(10)
+        .into()
+    }
+
+    fn response_tabs(&self) -> Element<'_, Message> {
+        let Some(response) = &self.last_response else {
+            return text(&self.reponse).into();
+        };
+        let tab = |label, show| {
+            let style = if self.show_connection == show {
+                theme::Button::Primary
+            } else {
+                theme::Button::Secondary
+            };
+            button(label)
+                .style(style)
+                .on_press(Message::ShowConnection(show))
+        };
+        let content = if self.show_connection {
+            match &response.connection {
+                Some(Ok(connection)) => text(format!("{connection}")),
+                Some(Err(e)) => text(format!("{e}")),
+                None if self.inspecting => text("reading the certificates..."),
+                None => text("no tls connection, the request was sent over plain http"),
+            }
+        } else {
+            text(&self.reponse)
+        };
+        column![
+            row![tab("response", false), tab("connection", true)].spacing(10),
+            content
+        ]
+        .spacing(10)
+        .into()
+    }
+
+    fn redirect_chain(&self) -> Element<'_, Message> {
+        let Some(response) = self
+            .last_response
+            .as_ref()
+            .filter(|r| !r.redirects.is_empty())
+        else {
+            return column![].into();
+        };
+        let hops: Vec<Element<Message>> = response
+            .hops()
+            .into_iter()
+            .enumerate()
+            .map(|(index, hop)| {
+                let target = match hop.location() {
+                    Some(location) => format!(" -> {location}"),
+                    None => String::new(),
+                };
+                let label = format!(
+                    "{0} {1} {2}{target} ({3}ms)",
+                    hop.status, hop.method, hop.url, hop.time_in_ms
+                );
+                let style = if index == self.selected_hop {
+                    theme::Button::Primary
+                } else {
+                    theme::Button::Secondary
+                };
+                button(text(label))
+                    .style(style)
+                    .on_press(Message::HopSelected(index))
+                    .into()
+            })
+            .collect();
+        column![text("redirects"), column(hops).spacing(5)]
+            .spacing(5)
+            .into()
+    }
+
+    // the view only reads the message, it is built again when the request changes
+    fn build_graphql_message(&mut self) {
+        self.graphql_message = self
+            .req_builder
+            .as_ref()
+            .filter(|b| b.graphql().is_some())
+            .and_then(|b| b.to_message().ok());
+    }
+
+    fn graphql_elements(&self) -> Element<'_, Message> {
+        let Some(message) = &self.graphql_message else {
+            return column![].into();
+        };
+        let cached = self.schemas.get(&message.url);
+        let status = match cached {
+            Some(cached) => format!("schema: {cached}"),
+            None => "no schema cached, only the syntax is checked".to_owned(),
+        };
+        let completions = cached
+            .map(|cached| cached.schema.complete(&self.schema_path).join("\n"))
+            .unwrap_or_default();
+        column![
+            row![
+                button("introspect schema").on_press(Message::IntrospectSchema),
+                text(status),
+            ]
+            .align_items(Alignment::Center)
+            .spacing(10),
+            text_input("field path, like user.na", &self.schema_path)
+                .on_input(Message::SchemaPathChanged),
+            text(completions),
+        ]
+        .spacing(10)
+        .into()
+    }
+
+    // queries are checked against the cached schema of their endpoint
+    fn graphql_errors(&self, message: &RequestMessage) -> Vec<String> {
+        match &message.graphql {
+            Some(graphql) => {
+                graphql.validate(self.schemas.get(&message.url).map(|cached| &cached.schema))
+            }
+            None => Vec::new(),
+        }
+    }
+
+    // the certificates are read the first time the connection tab shows a response
+    fn inspect_connection(&mut self) -> Command<Message> {
+        let peer = self
+            .last_response
+            .as_mut()
+            .and_then(|response| response.peer.take());
+        match peer {
+            Some(peer) => {
+                self.inspecting = true;
+                Command::perform(peer.inspect(), Message::ConnectionInspected)
+            }
+            None => Command::none(),
+        }
+    }
+
     fn notify(&mut self, message: &str) {
         // TODO implement actual notification
+        self.clear_result();
         self.reponse = message.to_string();
     }
 
+    fn clear_result(&mut self) {
+        self.reponse = String::new();
+        self.last_response = None;
+        self.inspecting = false;
+        self.events = None;
+        self.transfer = None;
+        self.grpc = None;
+        self.websocket = None;
+    }
+
     // replace with actual implementation
     fn get_builder_from_file(file_path: &str) -> Result<RequestMessageBuilder, RequestError> {
         match fs::read_to_string(file_path) {
@@ -204,5 +769,6 @@ impl Root {
             Err(_) => Err(RequestError::CouldNotReadFile),
         }
         .and_then(|toml_text| request_message::RequestMessage::from_text(&toml_text))
+        .map(|rmb| rmb.relative_to(Path::new(file_path).parent().unwrap_or(Path::new(""))))
     }
 }
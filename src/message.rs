@@ -1,3 +1,10 @@
+use crate::body_stream::TransferUpdate;
+use crate::certificate::ConnectionInfo;
+use crate::event_stream::StreamUpdate;
+use crate::grpc::GrpcUpdate;
+use crate::request_error::RequestError;
+use crate::websocket::WebSocketUpdate;
+
 #[derive(Debug, Clone)]
 pub enum Message {
     BaseFileChanged(String),
@@ -6,4 +13,27 @@ pub enum Message {
     FileTreeItemToogled(String),
     FileSelected(String),
     Send,
+    RefreshToken,
+    IntrospectSchema,
+    SchemaPathChanged(String),
+    HopSelected(usize),
+    ShowConnection(bool),
+    ConnectionInspected(Result<ConnectionInfo, RequestError>),
+    TransferUpdate(TransferUpdate),
+    StopTransfer,
+    GrpcUpdate(GrpcUpdate),
+    StopGrpc,
+    EventStreamUpdate(StreamUpdate),
+    StopEvents,
+    WebSocketUpdate(WebSocketUpdate),
+    WebSocketDraftChanged(String),
+    WebSocketBinaryToggled(bool),
+    WebSocketSend,
+    WebSocketClose,
+    ClearEvents,
+    ReconnectEventsToggled(bool),
+    ToggleCookies,
+    CookieValueChanged(usize, String),
+    CookieRemoved(usize),
+    PersistCookiesToggled(bool),
 }
@@ -0,0 +1,255 @@
+use std::{
+    fmt::{Debug, Display},
+    time::{Duration, Instant},
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use isahc::{config::Configurable, http::Request, AsyncReadResponseExt};
+use serde::Deserialize;
+use url::Url;
+
+use crate::http_client;
+use crate::proxy;
+use crate::request_error::RequestError;
+use crate::request_message::RequestMessage;
+use crate::session::{Session, Token};
+
+// tokens are renewed a little before they expire so they are still valid when the request arrives
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Deserialize)]
+pub struct OAuth2Config {
+    token_url: String,
+    client_id: String,
+    #[serde(default)]
+    client_secret: String,
+    #[serde(default)]
+    grant: Grant,
+    username: Option<String>,
+    password: Option<String>,
+    scope: Option<String>,
+    #[serde(default)]
+    client_auth: ClientAuth,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Grant {
+    #[default]
+    ClientCredentials,
+    Password,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ClientAuth {
+    #[default]
+    Basic,
+    Body,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+impl OAuth2Config {
+    // the token endpoint is reached with the tls, proxy and timeout of the request it authorizes
+    pub async fn access_token(
+        &self,
+        request_message: &RequestMessage,
+        session: &Session,
+    ) -> Result<String, RequestError> {
+        let key = self.cache_key();
+        let refresh_token = match session.token(&key) {
+            Some(token) if is_valid(&token) => return Ok(token.access_token),
+            Some(token) => token.refresh_token,
+            None => None,
+        };
+
+        let token = self.renew(request_message, refresh_token).await?;
+        session.store_token(&key, token.clone());
+        Ok(token.access_token)
+    }
+
+    pub async fn refresh(
+        &self,
+        request_message: &RequestMessage,
+        session: &Session,
+    ) -> Result<(), RequestError> {
+        let key = self.cache_key();
+        let refresh_token = session.take_token(&key).and_then(|t| t.refresh_token);
+
+        let token = self.renew(request_message, refresh_token).await?;
+        session.store_token(&key, token);
+        Ok(())
+    }
+
+    pub fn status(&self, session: &Session) -> String {
+        match session.token(&self.cache_key()) {
+            None => "no token".to_owned(),
+            Some(token) => match token.expires_at {
+                None => "valid, no expiry".to_owned(),
+                Some(_) if !is_valid(&token) => "expired".to_owned(),
+                Some(expires_at) => format!(
+                    "valid, expires in {0}s",
+                    expires_at
+                        .saturating_duration_since(Instant::now())
+                        .as_secs()
+                ),
+            },
+        }
+    }
+
+    fn cache_key(&self) -> String {
+        format!(
+            "{0}|{1}|{2}|{3}",
+            self.token_url,
+            self.client_id,
+            self.username.as_deref().unwrap_or(""),
+            self.scope.as_deref().unwrap_or("")
+        )
+    }
+
+    async fn renew(
+        &self,
+        request_message: &RequestMessage,
+        refresh_token: Option<String>,
+    ) -> Result<Token, RequestError> {
+        if let Some(refresh_token) = refresh_token {
+            let params = vec![
+                ("grant_type", "refresh_token".to_owned()),
+                ("refresh_token", refresh_token),
+            ];
+            // a rejected refresh token falls back to a new grant
+            if let Ok(token) = self.request_token(request_message, params).await {
+                return Ok(token);
+            }
+        }
+
+        let params = match self.grant {
+            Grant::ClientCredentials => vec![("grant_type", "client_credentials".to_owned())],
+            Grant::Password => vec![
+                ("grant_type", "password".to_owned()),
+                ("username", required(&self.username, "auth.username")?),
+                ("password", required(&self.password, "auth.password")?),
+            ],
+        };
+        self.request_token(request_message, params).await
+    }
+
+    async fn request_token(
+        &self,
+        request_message: &RequestMessage,
+        mut params: Vec<(&str, String)>,
+    ) -> Result<Token, RequestError> {
+        let auth_error = |message: String| RequestError::AuthError { message };
+
+        if let Some(scope) = &self.scope {
+            params.push(("scope", scope.clone()));
+        }
+        let token_url = Url::parse(&self.token_url).map_err(|e| RequestError::InvalidUrl {
+            url: self.token_url.clone(),
+            message: e.to_string(),
+        })?;
+        let mut request = Request::post(token_url.as_str())
+            .header("Accept", "application/json")
+            .header("Content-Type", "application/x-www-form-urlencoded");
+        if let Some(timeout) = request_message.timeout {
+            request = request.timeout(timeout);
+        }
+        request = match proxy::resolve(request_message.proxy.as_ref(), &token_url) {
+            Some(proxy) => proxy.configure(request),
+            None => request.proxy(None),
+        };
+        match self.client_auth {
+            ClientAuth::Basic => {
+                let credentials =
+                    STANDARD.encode(format!("{0}:{1}", self.client_id, self.client_secret));
+                request = request.header("Authorization", format!("Basic {credentials}"));
+            }
+            ClientAuth::Body => {
+                params.push(("client_id", self.client_id.clone()));
+                params.push(("client_secret", self.client_secret.clone()));
+            }
+        }
+        let form = url::form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(params)
+            .finish();
+
+        let requested_at = Instant::now();
+        let request_failed = |e: isahc::Error| {
+            auth_error(format!("token request to {0} failed: {e}", self.token_url))
+        };
+        let request = request.body(form).map_err(|e| request_failed(e.into()))?;
+        let mut response = http_client::client(request_message)?
+            .send_async(request)
+            .await
+            .map_err(request_failed)?;
+        let body = response
+            .text()
+            .await
+            .map_err(|e| auth_error(format!("could not read token response: {e}")))?;
+        if !response.status().is_success() {
+            return Err(auth_error(format!(
+                "token endpoint answered {0}: {body}",
+                response.status()
+            )));
+        }
+
+        let token_response = serde_json::from_str::<TokenResponse>(&body)
+            .map_err(|e| auth_error(format!("invalid token response: {e}")))?;
+        Ok(Token {
+            access_token: token_response.access_token,
+            expires_at: token_response
+                .expires_in
+                .map(|seconds| requested_at + Duration::from_secs(seconds)),
+            refresh_token: token_response.refresh_token,
+        })
+    }
+}
+
+fn required(value: &Option<String>, property_name: &str) -> Result<String, RequestError> {
+    value.clone().ok_or_else(|| RequestError::InvalidValue {
+        property_name: property_name.to_owned(),
+        message: "is required for the password grant".to_owned(),
+    })
+}
+
+fn is_valid(token: &Token) -> bool {
+    match token.expires_at {
+        Some(expires_at) => Instant::now() + EXPIRY_MARGIN < expires_at,
+        None => true,
+    }
+}
+
+impl Debug for OAuth2Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("OAuth2Config")
+            .field("token_url", &self.token_url)
+            .field("client_id", &self.client_id)
+            .field("client_secret", &"***")
+            .field("grant", &self.grant)
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "***"))
+            .field("scope", &self.scope)
+            .field("client_auth", &self.client_auth)
+            .finish()
+    }
+}
+
+impl Display for OAuth2Config {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let grant = match self.grant {
+            Grant::ClientCredentials => "client_credentials",
+            Grant::Password => "password",
+        };
+        write!(
+            f,
+            "oauth2 {grant}, client {0}, token url {1}",
+            self.client_id, self.token_url
+        )
+    }
+}
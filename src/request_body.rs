@@ -0,0 +1,369 @@
+use std::{
+    fs,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+use rand::{distributions::Alphanumeric, Rng};
+
+use crate::compression::CompressedFile;
+use crate::request_error::RequestError;
+
+#[derive(Clone)]
+pub enum RequestBody {
+    Text(String),
+    Bytes(Vec<u8>),
+    File(PathBuf),
+    CompressedFile(CompressedFile),
+}
+
+pub struct EncodedBody {
+    pub content_type: String,
+    pub body: RequestBody,
+}
+
+enum MultipartPart {
+    Text {
+        name: String,
+        value: String,
+        content_type: Option<String>,
+    },
+    File {
+        name: String,
+        path: String,
+        file_name: Option<String>,
+        content_type: Option<String>,
+    },
+}
+
+pub fn table_pairs(table: &toml::Table) -> Result<Vec<(String, String)>, String> {
+    let mut pairs = Vec::new();
+    for (key, value) in table {
+        match value {
+            toml::Value::Array(values) => {
+                for item in values {
+                    pairs.push((key.clone(), scalar_value(key, item)?));
+                }
+            }
+            other => pairs.push((key.clone(), scalar_value(key, other)?)),
+        }
+    }
+    Ok(pairs)
+}
+
+fn scalar_value(key: &str, value: &toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(i) => Ok(i.to_string()),
+        toml::Value::Float(f) => Ok(f.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        toml::Value::Datetime(d) => Ok(d.to_string()),
+        _ => Err(format!("{key} must be a scalar or a list of scalars")),
+    }
+}
+
+pub fn form_urlencoded(form: &toml::Table) -> Result<EncodedBody, RequestError> {
+    let pairs = table_pairs(form).map_err(|message| RequestError::InvalidValue {
+        property_name: "form".to_owned(),
+        message,
+    })?;
+    let encoded = url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(pairs)
+        .finish();
+
+    Ok(EncodedBody {
+        content_type: "application/x-www-form-urlencoded".to_owned(),
+        body: RequestBody::Text(encoded),
+    })
+}
+
+pub fn multipart(form: &toml::Table) -> Result<EncodedBody, RequestError> {
+    let mut parts = Vec::new();
+    for part in multipart_parts(form)? {
+        let mut body = Vec::new();
+        match part {
+            MultipartPart::Text {
+                name,
+                value,
+                content_type,
+            } => {
+                body.extend_from_slice(
+                    format!(
+                        "Content-Disposition: form-data; name=\"{0}\"\r\n",
+                        escape_quoted(&name)
+                    )
+                    .as_bytes(),
+                );
+                if let Some(content_type) = content_type {
+                    body.extend_from_slice(format!("Content-Type: {content_type}\r\n").as_bytes());
+                }
+                body.extend_from_slice(b"\r\n");
+                body.extend_from_slice(value.as_bytes());
+            }
+            MultipartPart::File {
+                name,
+                path,
+                file_name,
+                content_type,
+            } => {
+                let content = fs::read(&path).map_err(|e| RequestError::InvalidValue {
+                    property_name: format!("multipart.{name}"),
+                    message: format!("could not read {path}: {e}"),
+                })?;
+                let file_name = file_name.unwrap_or_else(|| {
+                    Path::new(&path)
+                        .file_name()
+                        .and_then(|n| n.to_str())
+                        .unwrap_or("file")
+                        .to_owned()
+                });
+                let content_type = content_type.unwrap_or_else(|| {
+                    mime_guess::from_path(&path)
+                        .first_or_octet_stream()
+                        .to_string()
+                });
+                body.extend_from_slice(
+                    format!(
+                        "Content-Disposition: form-data; name=\"{0}\"; filename=\"{1}\"\r\n",
+                        escape_quoted(&name),
+                        escape_quoted(&file_name)
+                    )
+                    .as_bytes(),
+                );
+                body.extend_from_slice(format!("Content-Type: {content_type}\r\n\r\n").as_bytes());
+                body.extend_from_slice(&content);
+            }
+        }
+        parts.push(body);
+    }
+
+    // picked again in the unlikely case a part contains it
+    let boundary = loop {
+        let boundary = boundary();
+        if !parts.iter().any(|part| contains(part, boundary.as_bytes())) {
+            break boundary;
+        }
+    };
+    let mut body = Vec::new();
+    for part in parts {
+        body.extend_from_slice(format!("--{boundary}\r\n").as_bytes());
+        body.extend_from_slice(&part);
+        body.extend_from_slice(b"\r\n");
+    }
+    body.extend_from_slice(format!("--{boundary}--\r\n").as_bytes());
+
+    Ok(EncodedBody {
+        content_type: format!("multipart/form-data; boundary={boundary}"),
+        body: RequestBody::Bytes(body),
+    })
+}
+
+fn boundary() -> String {
+    let random: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(24)
+        .map(char::from)
+        .collect();
+    format!("----ghucl{random}")
+}
+
+fn contains(bytes: &[u8], pattern: &[u8]) -> bool {
+    bytes.windows(pattern.len()).any(|window| window == pattern)
+}
+
+pub fn file(path: &str) -> Result<RequestBody, RequestError> {
+    match fs::metadata(path) {
+        Ok(metadata) if metadata.is_file() => Ok(RequestBody::File(PathBuf::from(path))),
+        Ok(_) => Err(RequestError::InvalidValue {
+            property_name: "body_file".to_owned(),
+            message: format!("{path} is not a file"),
+        }),
+        Err(e) => Err(RequestError::InvalidValue {
+            property_name: "body_file".to_owned(),
+            message: format!("could not read {path}: {e}"),
+        }),
+    }
+}
+
+pub fn file_preview(path: &str) -> String {
+    const PREVIEW_SIZE: u64 = 1024;
+
+    let size = match fs::metadata(path) {
+        Ok(metadata) => metadata.len(),
+        Err(e) => return format!("{path}\ncould not read file: {e}"),
+    };
+    let mut preview = Vec::new();
+    let read = fs::File::open(path).and_then(|f| f.take(PREVIEW_SIZE).read_to_end(&mut preview));
+    let content = match (read, std::str::from_utf8(&preview)) {
+        (Err(e), _) => format!("could not read file: {e}"),
+        (Ok(_), Ok(text)) if !text.contains('\0') => text.to_owned(),
+        // a multi byte character may have been cut at the end of the preview
+        (Ok(_), Err(e)) if e.error_len().is_none() => {
+            String::from_utf8_lossy(&preview[..e.valid_up_to()]).to_string()
+        }
+        (Ok(_), _) => "[binary content]".to_owned(),
+    };
+    let ellipsis = if size > PREVIEW_SIZE { "\n..." } else { "" };
+
+    format!("{path} ({size} bytes)\n{content}{ellipsis}")
+}
+
+pub fn resolve_multipart_paths(form: &mut toml::Table, dir: &Path) {
+    fn resolve(value: &mut toml::Value, dir: &Path) {
+        match value {
+            toml::Value::Table(part) => {
+                if let Some(toml::Value::String(file)) = part.get_mut("file") {
+                    *file = dir.join(&*file).to_string_lossy().to_string();
+                }
+            }
+            toml::Value::Array(items) => items.iter_mut().for_each(|item| resolve(item, dir)),
+            _ => {}
+        }
+    }
+
+    for (_, value) in form.iter_mut() {
+        resolve(value, dir);
+    }
+}
+
+fn multipart_parts(form: &toml::Table) -> Result<Vec<MultipartPart>, RequestError> {
+    let mut parts = Vec::new();
+    for (name, value) in form {
+        match value {
+            toml::Value::Array(items) => {
+                for item in items {
+                    parts.push(multipart_part(name, item)?);
+                }
+            }
+            other => parts.push(multipart_part(name, other)?),
+        }
+    }
+    Ok(parts)
+}
+
+fn multipart_part(name: &str, value: &toml::Value) -> Result<MultipartPart, RequestError> {
+    let invalid = |message: &str| RequestError::InvalidValue {
+        property_name: format!("multipart.{name}"),
+        message: message.to_owned(),
+    };
+    let string_field = |part: &toml::Table, field: &str| match part.get(field) {
+        Some(toml::Value::String(s)) => Ok(Some(s.clone())),
+        Some(_) => Err(invalid(&format!("{field} must be a string"))),
+        None => Ok(None),
+    };
+
+    match value {
+        toml::Value::Table(part) => {
+            let content_type = string_field(part, "content_type")?;
+            match (string_field(part, "file")?, part.get("value")) {
+                (Some(path), None) => Ok(MultipartPart::File {
+                    name: name.to_owned(),
+                    path,
+                    file_name: string_field(part, "filename")?,
+                    content_type,
+                }),
+                (None, Some(value)) => Ok(MultipartPart::Text {
+                    name: name.to_owned(),
+                    value: scalar_value(name, value).map_err(|m| invalid(&m))?,
+                    content_type,
+                }),
+                _ => Err(invalid("a part needs either a file or a value")),
+            }
+        }
+        scalar => Ok(MultipartPart::Text {
+            name: name.to_owned(),
+            value: scalar_value(name, scalar).map_err(|m| invalid(&m))?,
+            content_type: None,
+        }),
+    }
+}
+
+fn escape_quoted(value: &str) -> String {
+    value
+        .replace('"', "%22")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(text: &str) -> toml::Table {
+        toml::from_str(text).unwrap()
+    }
+
+    fn text(body: &RequestBody) -> String {
+        match body {
+            RequestBody::Text(text) => text.clone(),
+            RequestBody::Bytes(bytes) => String::from_utf8_lossy(bytes).to_string(),
+            _ => panic!("expected an encoded body"),
+        }
+    }
+
+    #[test]
+    fn escapes_reserved_characters_in_forms() {
+        let encoded = form_urlencoded(&table(
+            "q = \"a&b=c d+e/é\"\ntags = [\"x\", 2]\n\"key with space\" = true",
+        ))
+        .unwrap();
+        assert_eq!(encoded.content_type, "application/x-www-form-urlencoded");
+        assert_eq!(
+            text(&encoded.body),
+            "q=a%26b%3Dc+d%2Be%2F%C3%A9&tags=x&tags=2&key+with+space=true"
+        );
+    }
+
+    #[test]
+    fn writes_text_and_file_parts_with_their_headers() {
+        let path = std::env::temp_dir().join(format!("ghucl-part-{0}.json", std::process::id()));
+        fs::write(&path, "{\"a\": 1}").unwrap();
+        let form = format!(
+            "name = \"Ada\"\n[note]\nvalue = \"hi\"\ncontent_type = \"text/plain\"\n[upload]\nfile = {0:?}\n[renamed]\nfile = {0:?}\nfilename = 'say \"hi\".txt'\ncontent_type = \"text/plain\"",
+            path.display().to_string()
+        );
+        let encoded = multipart(&table(&form));
+        fs::remove_file(&path).unwrap();
+        let encoded = encoded.unwrap();
+
+        let boundary = encoded
+            .content_type
+            .strip_prefix("multipart/form-data; boundary=")
+            .unwrap()
+            .to_owned();
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        let expected = [
+            "Content-Disposition: form-data; name=\"name\"\r\n\r\nAda".to_owned(),
+            "Content-Disposition: form-data; name=\"note\"\r\nContent-Type: text/plain\r\n\r\nhi"
+                .to_owned(),
+            format!("Content-Disposition: form-data; name=\"upload\"; filename=\"{file_name}\"\r\nContent-Type: application/json\r\n\r\n{{\"a\": 1}}"),
+            "Content-Disposition: form-data; name=\"renamed\"; filename=\"say %22hi%22.txt\"\r\nContent-Type: text/plain\r\n\r\n{\"a\": 1}".to_owned(),
+        ]
+        .iter()
+        .map(|part| format!("--{boundary}\r\n{part}\r\n"))
+        .collect::<String>();
+        assert_eq!(text(&encoded.body), format!("{expected}--{boundary}--\r\n"));
+    }
+
+    #[test]
+    fn boundary_does_not_occur_in_any_part() {
+        let form = table("a = \"----ghucl\"\nb = \"--\"");
+        for _ in 0..100 {
+            let encoded = multipart(&form).unwrap();
+            let boundary = encoded.content_type.rsplit_once('=').unwrap().1.to_owned();
+            assert_eq!(boundary.len(), 33);
+            let body = text(&encoded.body);
+            assert_eq!(body.matches(&boundary).count(), 3);
+        }
+    }
+
+    #[test]
+    fn reports_unreadable_file_parts() {
+        let error = multipart(&table("[upload]\nfile = \"/nonexistent/part.bin\""))
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("invalid multipart.upload: could not read /nonexistent/part.bin"));
+    }
+}
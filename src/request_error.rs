@@ -1,13 +1,44 @@
 use std::fmt::{self};
 
-use surf::Error;
+use isahc::Error;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum RequestError {
     CouldNotReadFile,
-    TomlParserError { message: String },
-    BuildError { property_name: String },
-    HttpError { inner: Error },
+    TomlParserError {
+        message: String,
+    },
+    BuildError {
+        property_name: String,
+    },
+    InvalidValue {
+        property_name: String,
+        message: String,
+    },
+    InvalidUrl {
+        url: String,
+        message: String,
+    },
+    AuthError {
+        message: String,
+    },
+    Timeout {
+        url: String,
+    },
+    ConnectionRefused {
+        url: String,
+    },
+    TooManyRedirects {
+        url: String,
+        max: u32,
+    },
+    TlsError {
+        url: String,
+        message: String,
+    },
+    HttpError {
+        inner: Error,
+    },
 }
 
 impl fmt::Display for RequestError {
@@ -20,6 +51,20 @@ impl fmt::Display for RequestError {
             RequestError::BuildError {
                 property_name: field,
             } => write!(f, "missing {0} information", field),
+            RequestError::InvalidValue {
+                property_name,
+                message,
+            } => write!(f, "invalid {0}: {1}", property_name, message),
+            RequestError::InvalidUrl { url, message } => {
+                write!(f, "invalid url {0}: {1}", url, message)
+            }
+            RequestError::AuthError { message } => write!(f, "authentication failed: {message}"),
+            RequestError::Timeout { url } => write!(f, "request to {url} timed out"),
+            RequestError::ConnectionRefused { url } => write!(f, "could not connect to {url}"),
+            RequestError::TooManyRedirects { url, max } => {
+                write!(f, "stopped at {url} after {max} redirects")
+            }
+            RequestError::TlsError { url, message } => write!(f, "tls error with {url}: {message}"),
             RequestError::HttpError { inner } => write!(f, "{inner}"),
         }
     }
@@ -1,12 +1,27 @@
 use std::{
     collections::HashMap,
     fmt::Display,
+    path::{Path, PathBuf},
+    time::Duration,
 };
 
-use serde::Deserialize;
+use serde::{de::Error as _, Deserialize, Deserializer};
+use url::Url;
 
+use crate::assertion::Assertions;
+use crate::auth::Auth;
+use crate::compression::CompressionConfig;
+use crate::graphql::GraphqlConfig;
+use crate::grpc::GrpcConfig;
+use crate::proxy::ProxyConfig;
+use crate::request_body::{self, RequestBody};
 use crate::request_error::RequestError;
+use crate::retry::RetryConfig;
+use crate::signature::SignatureConfig;
+use crate::tls::TlsConfig;
 
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Clone)]
 pub enum HttpVerb {
     GET,
     HEAD,
@@ -15,82 +30,491 @@ pub enum HttpVerb {
     DELETE,
     OPTIONS,
     PATCH,
+    Custom(String),
 }
 
+impl Display for HttpVerb {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            HttpVerb::GET => "GET",
+            HttpVerb::HEAD => "HEAD",
+            HttpVerb::POST => "POST",
+            HttpVerb::PUT => "PUT",
+            HttpVerb::DELETE => "DELETE",
+            HttpVerb::OPTIONS => "OPTIONS",
+            HttpVerb::PATCH => "PATCH",
+            HttpVerb::Custom(name) => name,
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl HttpVerb {
+    pub fn is_idempotent(&self) -> bool {
+        match self {
+            HttpVerb::POST | HttpVerb::PATCH => false,
+            HttpVerb::Custom(name) => IDEMPOTENT_EXTENSION_METHODS.contains(&name.as_str()),
+            _ => true,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum Protocol {
+    Http,
+    WebSocket,
+    Grpc,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+pub enum HttpVersion {
+    Negotiated,
+    Http11,
+    Http2,
+    H2c,
+}
+
+impl Display for HttpVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            HttpVersion::Negotiated => "negotiated",
+            HttpVersion::Http11 => "HTTP/1.1",
+            HttpVersion::Http2 => "HTTP/2",
+            HttpVersion::H2c => "HTTP/2 (h2c)",
+        };
+        write!(f, "{name}")
+    }
+}
+
+#[derive(Clone)]
 pub struct RequestMessage {
+    pub protocol: Protocol,
+    pub http_version: HttpVersion,
     pub method: HttpVerb,
     pub url: String,
-    pub body: String,
+    pub body: RequestBody,
     pub headers: HashMap<String, String>,
+    pub auth: Option<Auth>,
+    // api keys sent in the query, kept out of url so they are never shown
+    pub auth_query: Vec<(String, String)>,
+    pub signature: Option<SignatureConfig>,
+    pub timeout: Option<Duration>,
+    pub follow_redirects: bool,
+    pub max_redirects: u32,
+    pub retry: Option<RetryConfig>,
+    pub tls: Option<TlsConfig>,
+    pub assert: Option<Assertions>,
+    pub proxy: Option<ProxyConfig>,
+    pub socket_path: Option<PathBuf>,
+    pub save_to: Option<PathBuf>,
+    pub messages: Vec<String>,
+    pub graphql: Option<GraphqlConfig>,
+    pub grpc: Option<GrpcConfig>,
+    pub compression: Option<CompressionConfig>,
 }
 
-#[derive(Default, Debug, Deserialize)]
+#[derive(Default, Debug, Clone, Deserialize)]
 pub struct RequestMessageBuilder {
+    protocol: Option<String>,
+    http_version: Option<String>,
     method: Option<String>,
     host: Option<String>,
     path: Option<String>,
     body: Option<String>,
+    body_file: Option<String>,
+    // the size and start of the body file, read once the file is resolved
+    #[serde(skip)]
+    body_file_preview: Option<String>,
+    json: Option<toml::Value>,
+    form: Option<toml::Table>,
+    multipart: Option<toml::Table>,
+    graphql: Option<GraphqlConfig>,
+    #[serde(default, deserialize_with = "deserialize_headers")]
     headers: Option<HashMap<String, String>>,
+    query: Option<toml::Table>,
+    auth: Option<Auth>,
+    signature: Option<SignatureConfig>,
+    timeout_ms: Option<u64>,
+    follow_redirects: Option<bool>,
+    max_redirects: Option<u32>,
+    retry: Option<RetryConfig>,
+    tls: Option<TlsConfig>,
+    assert: Option<Assertions>,
+    proxy: Option<ProxyConfig>,
+    socket_path: Option<String>,
+    custom_method: Option<bool>,
+    save_to: Option<String>,
+    messages: Option<Vec<String>>,
+    grpc: Option<GrpcConfig>,
+    compression: Option<CompressionConfig>,
+    unset: Option<Vec<String>>,
 }
 
+const UNSETTABLE_FIELDS: [&str; 28] = [
+    "protocol",
+    "http_version",
+    "method",
+    "host",
+    "path",
+    "body",
+    "body_file",
+    "json",
+    "form",
+    "multipart",
+    "graphql",
+    "headers",
+    "query",
+    "auth",
+    "signature",
+    "timeout_ms",
+    "follow_redirects",
+    "max_redirects",
+    "retry",
+    "tls",
+    "assert",
+    "proxy",
+    "socket_path",
+    "custom_method",
+    "save_to",
+    "messages",
+    "grpc",
+    "compression",
+];
+// registered methods accepted without custom_method, mostly from webdav and caches
+const EXTENSION_METHODS: [&str; 19] = [
+    "BAN",
+    "CONNECT",
+    "COPY",
+    "LINK",
+    "LOCK",
+    "MKCALENDAR",
+    "MKCOL",
+    "MOVE",
+    "PROPFIND",
+    "PROPPATCH",
+    "PURGE",
+    "QUERY",
+    "REPORT",
+    "SEARCH",
+    "TRACE",
+    "UNLINK",
+    "UNLOCK",
+    "UPDATE",
+    "VERSION-CONTROL",
+];
+// the extension methods the IANA method registry lists as idempotent
+const IDEMPOTENT_EXTENSION_METHODS: [&str; 15] = [
+    "COPY",
+    "LINK",
+    "MKCALENDAR",
+    "MKCOL",
+    "MOVE",
+    "PROPFIND",
+    "PROPPATCH",
+    "QUERY",
+    "REPORT",
+    "SEARCH",
+    "TRACE",
+    "UNLINK",
+    "UNLOCK",
+    "UPDATE",
+    "VERSION-CONTROL",
+];
+const UNIX_SCHEME: &str = "unix://";
+const DEFAULT_MAX_REDIRECTS: u32 = 10;
+const HEADER_UNSET_PREFIX: &str = "headers.";
+const QUERY_UNSET_PREFIX: &str = "query.";
+
 impl RequestMessage {
     pub fn from_text(file_text: &str) -> Result<RequestMessageBuilder, RequestError> {
-        let parsed = toml::from_str::<RequestMessageBuilder>(&file_text);
-
-        return parsed.map_err(|e| -> RequestError {
-            return RequestError::TomlParserError {
+        let parsed = toml::from_str::<RequestMessageBuilder>(file_text).map_err(|e| {
+            RequestError::TomlParserError {
                 message: e.message().to_string(),
-            };
-        });
+            }
+        })?;
+
+        for field in parsed.unset.iter().flatten() {
+            let is_entry = [HEADER_UNSET_PREFIX, QUERY_UNSET_PREFIX]
+                .iter()
+                .any(|prefix| {
+                    field
+                        .strip_prefix(prefix)
+                        .is_some_and(|name| !name.is_empty())
+                });
+            if !is_entry && !UNSETTABLE_FIELDS.contains(&field.as_str()) {
+                return Err(RequestError::InvalidValue {
+                    property_name: "unset".to_owned(),
+                    message: format!("unknown field {field}"),
+                });
+            }
+        }
+
+        Ok(parsed)
+    }
+
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        remove_header(&mut self.headers, name);
+        self.headers.insert(name.to_owned(), value.to_owned());
+    }
+
+    pub fn remove_header(&mut self, name: &str) {
+        remove_header(&mut self.headers, name);
+    }
+
+    // the url as it is sent, with the api key of the auth
+    pub fn wire_url(&self) -> String {
+        if self.auth_query.is_empty() {
+            return self.url.clone();
+        }
+        match Url::parse(&self.url) {
+            Ok(mut url) => {
+                url.query_pairs_mut().extend_pairs(self.auth_query.iter());
+                url.to_string()
+            }
+            Err(_) => self.url.clone(),
+        }
     }
 }
 
 impl RequestMessageBuilder {
     pub fn merge_with(&self, new_message: &RequestMessageBuilder) -> RequestMessageBuilder {
-        fn increment_header(
-            opt_headers: Option<HashMap<String, String>>,
-            mut target_headers: HashMap<String, String>,
-        ) -> HashMap<String, String> {
-            if let Some(cur_headers) = opt_headers {
-                for (k, v) in cur_headers {
-                    target_headers.insert(k, v);
-                }
-            }
-            return target_headers;
-        }
-        
+        fn inherit<T: Clone>(unset: &[String], field: &str, value: &Option<T>) -> Option<T> {
+            if unset.iter().any(|u| u == field) {
+                None
+            } else {
+                value.clone()
+            }
+        }
+
+        let unset = new_message.unset.clone().unwrap_or_default();
+        let is_unset = |field: &str| unset.iter().any(|u| u == field);
+
+        let protocol = new_message
+            .protocol
+            .clone()
+            .or(inherit(&unset, "protocol", &self.protocol));
+        let http_version = new_message.http_version.clone().or(inherit(
+            &unset,
+            "http_version",
+            &self.http_version,
+        ));
         let method = new_message
             .method
-            .as_ref()
-            .or(self.method.as_ref())
-            .and_then(|v| Some(v.clone()));
+            .clone()
+            .or(inherit(&unset, "method", &self.method));
         let host = new_message
             .host
-            .as_ref()
-            .or(self.host.as_ref())
-            .and_then(|v| Some(v.clone()));
+            .clone()
+            .or(inherit(&unset, "host", &self.host));
         let path = new_message
             .path
-            .as_ref()
-            .or(self.path.as_ref())
-            .and_then(|v| Some(v.clone()));
-        let body = new_message
-            .body
-            .as_ref()
-            .or(self.body.as_ref())
-            .and_then(|v| Some(v.clone()));
-
-        let copied_values = increment_header(self.headers.clone(), HashMap::new());
-        let incremented = increment_header(new_message.headers.clone(), copied_values);
-
-        let headers = Some(incremented);
-        return RequestMessageBuilder {
+            .clone()
+            .or(inherit(&unset, "path", &self.path));
+        let auth = new_message
+            .auth
+            .clone()
+            .or(inherit(&unset, "auth", &self.auth));
+        let signature =
+            new_message
+                .signature
+                .clone()
+                .or(inherit(&unset, "signature", &self.signature));
+        let timeout_ms = new_message
+            .timeout_ms
+            .or(inherit(&unset, "timeout_ms", &self.timeout_ms));
+        let follow_redirects = new_message.follow_redirects.or(inherit(
+            &unset,
+            "follow_redirects",
+            &self.follow_redirects,
+        ));
+        let max_redirects =
+            new_message
+                .max_redirects
+                .or(inherit(&unset, "max_redirects", &self.max_redirects));
+        let retry = new_message
+            .retry
+            .clone()
+            .or(inherit(&unset, "retry", &self.retry));
+        let tls = new_message
+            .tls
+            .clone()
+            .or(inherit(&unset, "tls", &self.tls));
+        let assert = new_message
+            .assert
+            .clone()
+            .or(inherit(&unset, "assert", &self.assert));
+        let proxy = new_message
+            .proxy
+            .clone()
+            .or(inherit(&unset, "proxy", &self.proxy));
+        let socket_path =
+            new_message
+                .socket_path
+                .clone()
+                .or(inherit(&unset, "socket_path", &self.socket_path));
+        let custom_method =
+            new_message
+                .custom_method
+                .or(inherit(&unset, "custom_method", &self.custom_method));
+        let save_to = new_message
+            .save_to
+            .clone()
+            .or(inherit(&unset, "save_to", &self.save_to));
+        let messages = new_message
+            .messages
+            .clone()
+            .or(inherit(&unset, "messages", &self.messages));
+        let grpc = new_message
+            .grpc
+            .clone()
+            .or(inherit(&unset, "grpc", &self.grpc));
+        let compression =
+            new_message
+                .compression
+                .clone()
+                .or(inherit(&unset, "compression", &self.compression));
+
+        // a request that brings its own body replaces the inherited one,
+        // except for json bodies that are merged into the base document
+        let replaces_body = new_message.has_body();
+        let body = match &new_message.body {
+            Some(body) => Some(body.clone()),
+            None if replaces_body => None,
+            None => inherit(&unset, "body", &self.body),
+        };
+        let (body_file, body_file_preview) = match &new_message.body_file {
+            Some(body_file) => (
+                Some(body_file.clone()),
+                new_message.body_file_preview.clone(),
+            ),
+            None if replaces_body => (None, None),
+            None => match inherit(&unset, "body_file", &self.body_file) {
+                Some(body_file) => (Some(body_file), self.body_file_preview.clone()),
+                None => (None, None),
+            },
+        };
+        let json = match (inherit(&unset, "json", &self.json), &new_message.json) {
+            (Some(base), Some(new)) => Some(merge_json(base, new)),
+            (base, None) if !replaces_body => base,
+            (_, new) => new.clone(),
+        };
+        let form = merge_body_table(
+            inherit(&unset, "form", &self.form),
+            &new_message.form,
+            replaces_body,
+        );
+        let multipart = merge_body_table(
+            inherit(&unset, "multipart", &self.multipart),
+            &new_message.multipart,
+            replaces_body,
+        );
+        let graphql = match &new_message.graphql {
+            Some(graphql) => Some(graphql.clone()),
+            None if replaces_body => None,
+            None => inherit(&unset, "graphql", &self.graphql),
+        };
+
+        let mut query = toml::Table::new();
+        if !is_unset("query") {
+            query.extend(self.query.clone().unwrap_or_default());
+        }
+        for name in unset
+            .iter()
+            .filter_map(|u| u.strip_prefix(QUERY_UNSET_PREFIX))
+        {
+            query.remove(name);
+        }
+        query.extend(new_message.query.clone().unwrap_or_default());
+
+        let mut headers = HashMap::new();
+        if !is_unset("headers") {
+            increment_header(self.headers.clone(), &mut headers);
+        }
+        for header_name in unset
+            .iter()
+            .filter_map(|u| u.strip_prefix(HEADER_UNSET_PREFIX))
+        {
+            remove_header(&mut headers, header_name);
+        }
+        increment_header(new_message.headers.clone(), &mut headers);
+
+        RequestMessageBuilder {
+            protocol,
+            http_version,
             method,
             host,
             path,
             body,
-            headers,
-        };
+            body_file,
+            body_file_preview,
+            json,
+            form,
+            multipart,
+            graphql,
+            headers: Some(headers),
+            query: Some(query),
+            auth,
+            signature,
+            timeout_ms,
+            follow_redirects,
+            max_redirects,
+            retry,
+            tls,
+            assert,
+            proxy,
+            socket_path,
+            custom_method,
+            save_to,
+            messages,
+            grpc,
+            compression,
+            unset: None,
+        }
+    }
+
+    pub fn auth(&self) -> Option<&Auth> {
+        self.auth.as_ref()
+    }
+
+    pub fn tls(&self) -> Option<&TlsConfig> {
+        self.tls.as_ref()
+    }
+
+    pub fn graphql(&self) -> Option<&GraphqlConfig> {
+        self.graphql.as_ref()
+    }
+
+    pub fn relative_to(mut self, dir: &Path) -> RequestMessageBuilder {
+        for file in [
+            self.body_file.as_mut(),
+            self.socket_path.as_mut(),
+            self.save_to.as_mut(),
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *file = dir.join(&*file).to_string_lossy().to_string();
+        }
+        self.body_file_preview = self.body_file.as_deref().map(request_body::file_preview);
+        if let Some(multipart) = self.multipart.as_mut() {
+            request_body::resolve_multipart_paths(multipart, dir);
+        }
+        if let Some(tls) = self.tls.as_mut() {
+            tls.relative_to(dir);
+        }
+        if let Some(grpc) = self.grpc.as_mut() {
+            grpc.relative_to(dir);
+        }
+        self
+    }
+
+    fn has_body(&self) -> bool {
+        self.body.is_some()
+            || self.body_file.is_some()
+            || self.json.is_some()
+            || self.form.is_some()
+            || self.multipart.is_some()
+            || self.graphql.is_some()
     }
 
     pub fn to_message(&self) -> Result<RequestMessage, RequestError> {
@@ -102,71 +526,671 @@ impl RequestMessageBuilder {
                 })
             }
         };
-        let path = match &self.path {
-            Some(x) => x,
-            None => {
+        let protocol = match self.protocol.as_deref() {
+            None | Some("http") => Protocol::Http,
+            Some("websocket") => Protocol::WebSocket,
+            Some("grpc") => Protocol::Grpc,
+            Some(other) => {
+                return Err(RequestError::InvalidValue {
+                    property_name: "protocol".to_owned(),
+                    message: format!("unknown protocol {other}, use http, websocket or grpc"),
+                })
+            }
+        };
+        let http_version = match self.http_version.as_deref() {
+            None => HttpVersion::Negotiated,
+            Some("1.1") => HttpVersion::Http11,
+            Some("2") => HttpVersion::Http2,
+            Some("h2c") => HttpVersion::H2c,
+            Some(other) => {
+                return Err(RequestError::InvalidValue {
+                    property_name: "http_version".to_owned(),
+                    message: format!("unknown http version {other}, use 1.1, 2 or h2c"),
+                })
+            }
+        };
+        // grpc calls are sent to the path of their method
+        let path = match (&self.path, &protocol, &self.grpc) {
+            (_, Protocol::Grpc, Some(grpc)) => grpc.path(),
+            (_, Protocol::Grpc, None) => {
+                return Err(RequestError::BuildError {
+                    property_name: "grpc".to_owned(),
+                })
+            }
+            (Some(x), _, _) => x.clone(),
+            (None, _, _) => {
                 return Err(RequestError::BuildError {
                     property_name: "path".to_owned(),
                 })
             }
         };
-
-        let method_candidate = match &self.method {
-            Some(x) => x,
-            None => {
+        // the websocket handshake is always a GET, graphql queries are posted
+        let method_candidate = match (&self.method, &protocol) {
+            (Some(x), _) => x.as_str(),
+            (None, Protocol::WebSocket) => "GET",
+            (None, Protocol::Grpc) => "POST",
+            (None, Protocol::Http) if self.graphql.is_some() => "POST",
+            (None, Protocol::Http) => {
                 return Err(RequestError::BuildError {
                     property_name: "method".to_owned(),
                 })
             }
         };
-        let body = match &self.body {
-            Some(s) => s.as_str(),
-            None => "",
+        let mut headers = HashMap::new();
+        increment_header(self.headers.clone(), &mut headers);
+
+        let body_kinds = [
+            self.body.is_some(),
+            self.body_file.is_some(),
+            self.json.is_some(),
+            self.form.is_some(),
+            self.multipart.is_some(),
+            self.graphql.is_some(),
+        ];
+        if body_kinds.iter().filter(|set| **set).count() > 1 {
+            return Err(RequestError::InvalidValue {
+                property_name: "body".to_owned(),
+                message:
+                    "only one of body, body_file, json, form, multipart or graphql can be used"
+                        .to_owned(),
+            });
+        }
+
+        let body = if let Some(json) = &self.json {
+            set_default_header(&mut headers, "Content-Type", "application/json");
+            RequestBody::Text(toml_to_json(json).to_string())
+        } else if let Some(graphql) = &self.graphql {
+            set_default_header(&mut headers, "Content-Type", "application/json");
+            RequestBody::Text(graphql.body())
+        } else if let Some(form) = &self.form {
+            let encoded = request_body::form_urlencoded(form)?;
+            set_default_header(&mut headers, "Content-Type", &encoded.content_type);
+            encoded.body
+        } else if let Some(multipart) = &self.multipart {
+            // the boundary is generated here so any content type set by hand is replaced
+            let encoded = request_body::multipart(multipart)?;
+            remove_header(&mut headers, "Content-Type");
+            headers.insert("Content-Type".to_owned(), encoded.content_type);
+            encoded.body
+        } else if let Some(body_file) = &self.body_file {
+            request_body::file(body_file)?
+        } else {
+            RequestBody::Text(self.body.clone().unwrap_or_default())
+        };
+
+        // a unix:// host names the socket, the requests are then sent for localhost
+        let (host, socket_path) = match host.strip_prefix(UNIX_SCHEME) {
+            Some(socket_path) => ("http://localhost", Some(socket_path)),
+            None => (host.as_str(), self.socket_path.as_deref()),
+        };
+        if socket_path.is_some_and(str::is_empty) {
+            return Err(RequestError::InvalidValue {
+                property_name: "socket_path".to_owned(),
+                message: "the socket path is empty".to_owned(),
+            });
+        }
+        // websocket urls are kept as http ones until the connection is opened,
+        // so auth, cookies and proxies treat them like any other request
+        let host = match (host.split_once("://"), &protocol) {
+            (Some(("ws", rest)), Protocol::WebSocket) => format!("http://{rest}"),
+            (Some(("wss", rest)), Protocol::WebSocket) => format!("https://{rest}"),
+            (Some(("ws" | "wss", _)), Protocol::Http) => {
+                return Err(RequestError::InvalidUrl {
+                    url: host.to_owned(),
+                    message: "websocket urls need protocol = \"websocket\"".to_owned(),
+                })
+            }
+            _ => host.to_owned(),
+        };
+        let url = build_url(&host, &path, self.query.as_ref())?;
+        // http/2 is offered during the tls handshake, cleartext servers need prior knowledge
+        let version_mismatch = match (http_version, url.scheme()) {
+            (HttpVersion::Http2, "http") => Some("HTTP/2 is negotiated over https, use h2c"),
+            (HttpVersion::H2c, "https") => Some("h2c is cleartext HTTP/2, use 2 over https"),
+            _ => None,
         };
+        if let Some(message) = version_mismatch.filter(|_| protocol == Protocol::Http) {
+            return Err(RequestError::InvalidValue {
+                property_name: "http_version".to_owned(),
+                message: message.to_owned(),
+            });
+        }
+
+        if self.save_to.as_deref().is_some_and(str::is_empty) {
+            return Err(RequestError::InvalidValue {
+                property_name: "save_to".to_owned(),
+                message: "the file path is empty".to_owned(),
+            });
+        }
+        if self.timeout_ms == Some(0) {
+            return Err(RequestError::InvalidValue {
+                property_name: "timeout_ms".to_owned(),
+                message: "must be greater than 0".to_owned(),
+            });
+        }
+        if let Some(tls) = &self.tls {
+            tls.validate()?;
+        }
+        if let Some(proxy) = &self.proxy {
+            proxy.validate()?;
+        }
+        if let Some(compression) = &self.compression {
+            compression.validate()?;
+        }
 
         return Ok(RequestMessage {
-            method: parse_method(method_candidate),
-            url: String::from(host) + path,
-            body: body.to_string(),
-            headers: self.headers.clone().unwrap_or(HashMap::new()),
+            protocol,
+            http_version,
+            method: parse_method(method_candidate, self.custom_method.unwrap_or(false))?,
+            url: url.to_string(),
+            body,
+            headers,
+            auth: self.auth.clone(),
+            auth_query: Vec::new(),
+            signature: self.signature.clone(),
+            timeout: self.timeout_ms.map(Duration::from_millis),
+            follow_redirects: self.follow_redirects.unwrap_or(false),
+            max_redirects: self.max_redirects.unwrap_or(DEFAULT_MAX_REDIRECTS),
+            retry: self.retry.clone(),
+            tls: self.tls.clone(),
+            assert: self.assert.clone(),
+            proxy: self.proxy.clone(),
+            socket_path: socket_path.map(PathBuf::from),
+            save_to: self.save_to.as_ref().map(PathBuf::from),
+            messages: self.messages.clone().unwrap_or_default(),
+            graphql: self.graphql.clone(),
+            grpc: self.grpc.clone(),
+            compression: self.compression.clone(),
         });
 
-        fn parse_method(candidate: &str) -> HttpVerb {
-            match candidate.to_uppercase().as_str() {
-                "GET" => HttpVerb::GET,
-                "HEAD" => HttpVerb::HEAD,
-                "POST" => HttpVerb::POST,
-                "PUT" => HttpVerb::PUT,
-                "DELETE" => HttpVerb::DELETE,
-                "OPTIONS" => HttpVerb::OPTIONS,
-                "PATCH" => HttpVerb::PATCH,
-                _ => HttpVerb::HEAD,
+        fn parse_method(candidate: &str, custom_method: bool) -> Result<HttpVerb, RequestError> {
+            let invalid = |message: String| RequestError::InvalidValue {
+                property_name: "method".to_owned(),
+                message,
+            };
+            let is_token = !candidate.is_empty()
+                && candidate
+                    .bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&b));
+            if !is_token {
+                return Err(invalid(format!("{candidate:?} is not a valid method name")));
+            }
+
+            let upper = candidate.to_uppercase();
+            match upper.as_str() {
+                "GET" => Ok(HttpVerb::GET),
+                "HEAD" => Ok(HttpVerb::HEAD),
+                "POST" => Ok(HttpVerb::POST),
+                "PUT" => Ok(HttpVerb::PUT),
+                "DELETE" => Ok(HttpVerb::DELETE),
+                "OPTIONS" => Ok(HttpVerb::OPTIONS),
+                "PATCH" => Ok(HttpVerb::PATCH),
+                known if EXTENSION_METHODS.contains(&known) => Ok(HttpVerb::Custom(upper)),
+                // methods are case sensitive, so an unregistered one is sent as written
+                _ if custom_method => Ok(HttpVerb::Custom(candidate.to_owned())),
+                _ => Err(invalid(format!(
+                    "unknown method {candidate}, set custom_method = true to send it anyway"
+                ))),
             }
         }
     }
 }
 
+fn build_url(host: &str, path: &str, query: Option<&toml::Table>) -> Result<Url, RequestError> {
+    let invalid = |message: String| RequestError::InvalidUrl {
+        url: format!("{host}{path}"),
+        message,
+    };
+
+    let mut url = Url::parse(host).map_err(|e| invalid(e.to_string()))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(invalid(format!("unsupported scheme {0}", url.scheme())));
+    }
+    if url.host_str().is_none_or(str::is_empty) {
+        return Err(invalid("host is missing".to_owned()));
+    }
+
+    let (path, path_query) = match path.split_once('?') {
+        Some((p, q)) => (p, Some(q)),
+        None => (path, None),
+    };
+    let joined_path = format!(
+        "{0}/{1}",
+        url.path().trim_end_matches('/'),
+        path.trim_start_matches('/')
+    );
+    url.set_path(&joined_path);
+
+    let raw_query = [url.query(), path_query]
+        .into_iter()
+        .flatten()
+        .filter(|q| !q.is_empty())
+        .collect::<Vec<&str>>()
+        .join("&");
+    url.set_query(if raw_query.is_empty() {
+        None
+    } else {
+        Some(&raw_query)
+    });
+
+    let pairs = request_body::table_pairs(query.unwrap_or(&toml::Table::new()))
+        .map_err(|message| invalid(format!("query parameter {message}")))?;
+    if !pairs.is_empty() {
+        url.query_pairs_mut().extend_pairs(pairs);
+    }
+
+    Ok(url)
+}
+
+fn merge_body_table(
+    base: Option<toml::Table>,
+    new: &Option<toml::Table>,
+    replaces_body: bool,
+) -> Option<toml::Table> {
+    match (base, new) {
+        (Some(mut base), Some(new)) => {
+            base.extend(new.clone());
+            Some(base)
+        }
+        (base, None) if !replaces_body => base,
+        (_, new) => new.clone(),
+    }
+}
+
+fn merge_json(base: toml::Value, new: &toml::Value) -> toml::Value {
+    match (base, new) {
+        (toml::Value::Table(mut base_table), toml::Value::Table(new_table)) => {
+            for (key, new_value) in new_table {
+                let merged = match base_table.remove(key) {
+                    Some(base_value) => merge_json(base_value, new_value),
+                    None => new_value.clone(),
+                };
+                base_table.insert(key.clone(), merged);
+            }
+            toml::Value::Table(base_table)
+        }
+        (_, new) => new.clone(),
+    }
+}
+
+pub fn toml_to_json(value: &toml::Value) -> serde_json::Value {
+    match value {
+        toml::Value::String(s) => serde_json::Value::from(s.as_str()),
+        toml::Value::Integer(i) => serde_json::Value::from(*i),
+        toml::Value::Float(f) => serde_json::Value::from(*f),
+        toml::Value::Boolean(b) => serde_json::Value::from(*b),
+        toml::Value::Datetime(d) => serde_json::Value::from(d.to_string()),
+        toml::Value::Array(items) => items.iter().map(toml_to_json).collect(),
+        toml::Value::Table(table) => serde_json::Value::Object(
+            table
+                .iter()
+                .map(|(k, v)| (k.clone(), toml_to_json(v)))
+                .collect(),
+        ),
+    }
+}
+
+fn set_default_header(target_headers: &mut HashMap<String, String>, name: &str, value: &str) {
+    if !target_headers.keys().any(|k| k.eq_ignore_ascii_case(name)) {
+        target_headers.insert(name.to_owned(), value.to_owned());
+    }
+}
+
+fn increment_header(
+    opt_headers: Option<HashMap<String, String>>,
+    target_headers: &mut HashMap<String, String>,
+) {
+    if let Some(cur_headers) = opt_headers {
+        for (k, v) in cur_headers {
+            remove_header(target_headers, &k);
+            target_headers.insert(k, v);
+        }
+    }
+}
+
+fn remove_header(target_headers: &mut HashMap<String, String>, name: &str) {
+    target_headers.retain(|k, _| !k.eq_ignore_ascii_case(name));
+}
+
+// read in file order, so of names differing only by case the last one written wins
+fn deserialize_headers<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<HashMap<String, String>>, D::Error> {
+    let mut headers = HashMap::new();
+    for (name, value) in toml::Table::deserialize(deserializer)? {
+        let toml::Value::String(value) = value else {
+            return Err(D::Error::custom(format!("header {name} must be a string")));
+        };
+        remove_header(&mut headers, &name);
+        headers.insert(name, value);
+    }
+    Ok(Some(headers))
+}
+
 impl Display for RequestMessageBuilder {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let empty = "[none]".to_string();
-        write!(f, "{0}", self.method.as_ref().unwrap_or(&empty))
-            .and_then(|_| write!(f, " {0}", self.host.as_ref().unwrap_or(&empty)))
-            .and_then(|_| write!(f, "{0}\n", self.path.as_ref().unwrap_or(&empty)))
-            .and_then(|_| writeln!(f, "\n body \n{0}", self.body.as_ref().unwrap_or(&empty)))
-            .and_then(|_| {
-                if let Some(headers) = &self.headers {
-                    let out_write_result = writeln!(f, "\nHeaders");
-                    if out_write_result.is_err() {
-                        return out_write_result;
-                    }
-                    for item in headers.iter() {
-                        let inner_result = writeln!(f, "{0}:{1}", item.0, item.1);
-                        if inner_result.is_err() {
-                            return inner_result;
-                        }
-                    }
-                }
-                Ok(())
-            })
+        if let Some(protocol) = &self.protocol {
+            write!(f, "{protocol} ")?;
+        }
+        write!(f, "{0}", self.method.as_ref().unwrap_or(&empty))?;
+        write!(f, " {0}", self.host.as_ref().unwrap_or(&empty))?;
+        writeln!(f, "{0}", self.path.as_ref().unwrap_or(&empty))?;
+        if let Some(json) = &self.json {
+            writeln!(
+                f,
+                "\n json \n{0}",
+                serde_json::to_string_pretty(&toml_to_json(json)).unwrap_or_default()
+            )?;
+        } else if let Some(graphql) = &self.graphql {
+            writeln!(f, "\n graphql \n{graphql}")?;
+        } else if let Some(form) = &self.form {
+            writeln!(f, "\n form \n{0}", form)?;
+        } else if let Some(multipart) = &self.multipart {
+            writeln!(f, "\n multipart \n{0}", multipart)?;
+        } else if let Some(body_file) = &self.body_file {
+            let preview = self.body_file_preview.as_ref().unwrap_or(body_file);
+            writeln!(f, "\n body file \n{preview}")?;
+        } else {
+            writeln!(f, "\n body \n{0}", self.body.as_ref().unwrap_or(&empty))?;
+        }
+        if let Some(headers) = &self.headers {
+            writeln!(f, "\nHeaders")?;
+            for item in headers.iter() {
+                writeln!(f, "{0}:{1}", item.0, item.1)?;
+            }
+        }
+        if let Some(query) = self.query.as_ref().filter(|q| !q.is_empty()) {
+            writeln!(f, "\nQuery")?;
+            for (key, value) in query.iter() {
+                writeln!(f, "{0}={1}", key, value)?;
+            }
+        }
+        if let Some(auth) = &self.auth {
+            writeln!(f, "\nAuth\n{0}", auth)?;
+        }
+        if let Some(signature) = &self.signature {
+            writeln!(f, "\nSignature\n{0}", signature)?;
+        }
+        if let Some(http_version) = &self.http_version {
+            writeln!(f, "\nHttp version\n{http_version}")?;
+        }
+        if let Some(timeout_ms) = self.timeout_ms {
+            writeln!(f, "\nTimeout\n{timeout_ms} ms")?;
+        }
+        if let Some(follow_redirects) = self.follow_redirects {
+            let max_redirects = self.max_redirects.unwrap_or(DEFAULT_MAX_REDIRECTS);
+            match follow_redirects {
+                true => writeln!(f, "\nRedirects\nfollowed, at most {max_redirects}")?,
+                false => writeln!(f, "\nRedirects\nnot followed")?,
+            }
+        }
+        if let Some(retry) = &self.retry {
+            writeln!(f, "\nRetry\n{retry}")?;
+        }
+        if let Some(tls) = &self.tls {
+            write!(f, "\nTls\n{tls}")?;
+        }
+        if let Some(socket_path) = &self.socket_path {
+            writeln!(f, "\nSocket\n{socket_path}")?;
+        }
+        if let Some(proxy) = &self.proxy {
+            writeln!(f, "\nProxy\n{proxy}")?;
+        }
+        if let Some(compression) = &self.compression {
+            write!(f, "\nCompression\n{compression}")?;
+        }
+        if let Some(save_to) = &self.save_to {
+            writeln!(f, "\nSave to\n{save_to}")?;
+        }
+        if let Some(grpc) = &self.grpc {
+            writeln!(f, "\nGrpc\n{grpc}")?;
+        }
+        if let Some(messages) = self.messages.as_ref().filter(|m| !m.is_empty()) {
+            writeln!(f, "\nMessages")?;
+            for message in messages {
+                writeln!(f, "{message}")?;
+            }
+        }
+        if let Some(assert) = &self.assert {
+            write!(f, "\nAssert\n{assert}")?;
+        }
+        if let Some(unset) = &self.unset {
+            writeln!(f, "\nUnset\n{0}", unset.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn merged(base: &str, request: &str) -> RequestMessage {
+        let base = RequestMessage::from_text(base).unwrap();
+        let request = RequestMessage::from_text(request).unwrap();
+        base.merge_with(&request).to_message().unwrap()
+    }
+
+    #[test]
+    fn request_overrides_and_extends_base() {
+        let message = merged(
+            "host = \"https://api.local\"\ntimeout_ms = 500\n[headers]\nAccept = \"text/plain\"\nX-Trace = \"1\"",
+            "method = \"POST\"\npath = \"/users\"\n[headers]\naccept = \"application/json\"",
+        );
+        assert_eq!(message.url, "https://api.local/users");
+        assert_eq!(message.method.to_string(), "POST");
+        assert_eq!(message.timeout, Some(Duration::from_millis(500)));
+        assert_eq!(message.headers.len(), 2);
+        assert!(message
+            .headers
+            .iter()
+            .any(|(k, v)| k.eq_ignore_ascii_case("accept") && v == "application/json"));
+    }
+
+    #[test]
+    fn last_header_differing_by_case_wins_within_a_file() {
+        for _ in 0..20 {
+            let message = merged(
+                "host = \"https://api.local\"",
+                "method = \"GET\"\npath = \"/\"\n[headers]\nx-token = \"first\"\nX-Token = \"second\"\nX-TOKEN = \"last\"",
+            );
+            assert_eq!(
+                message.headers,
+                HashMap::from([("X-TOKEN".to_owned(), "last".to_owned())])
+            );
+        }
+        let error = RequestMessage::from_text("[headers]\nX-Retries = 3").unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("header X-Retries must be a string"));
+    }
+
+    #[test]
+    fn unset_drops_inherited_fields_and_entries() {
+        let message = merged(
+            "host = \"https://api.local\"\ntimeout_ms = 500\n[headers]\nX-Trace = \"1\"\nX-Keep = \"2\"\n[query]\nkey = \"k\"\npage = 1",
+            "method = \"GET\"\npath = \"/\"\nunset = [\"timeout_ms\", \"headers.x-trace\", \"query.key\"]",
+        );
+        assert_eq!(message.timeout, None);
+        assert_eq!(
+            message.headers,
+            HashMap::from([("X-Keep".to_owned(), "2".to_owned())])
+        );
+        assert_eq!(message.url, "https://api.local/?page=1");
+    }
+
+    #[test]
+    fn unset_whole_table_keeps_request_entries() {
+        let message = merged(
+            "host = \"https://api.local\"\n[headers]\nX-Trace = \"1\"",
+            "method = \"GET\"\npath = \"/\"\nunset = [\"headers\"]\n[headers]\nX-Own = \"2\"",
+        );
+        assert_eq!(
+            message.headers,
+            HashMap::from([("X-Own".to_owned(), "2".to_owned())])
+        );
+    }
+
+    #[test]
+    fn reports_a_missing_body_file() {
+        let error = RequestMessage::from_text(
+            "method = \"POST\"\nhost = \"https://api.local\"\npath = \"/\"\nbody_file = \"missing.json\"",
+        )
+        .unwrap()
+        .relative_to(Path::new("/nonexistent"))
+        .to_message()
+        .err()
+        .unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("invalid body_file: could not read /nonexistent/missing.json: "));
+    }
+
+    #[test]
+    fn reads_the_body_file_once_for_display() {
+        let dir = std::env::temp_dir();
+        let name = format!("ghucl-display-{0}.txt", std::process::id());
+        std::fs::write(dir.join(&name), "hello").unwrap();
+        let base = RequestMessage::from_text("host = \"https://api.local\"")
+            .unwrap()
+            .relative_to(&dir);
+        let request = RequestMessage::from_text(&format!(
+            "method = \"POST\"\npath = \"/\"\nbody_file = \"{name}\""
+        ))
+        .unwrap()
+        .relative_to(&dir);
+        std::fs::remove_file(dir.join(&name)).unwrap();
+        let shown = base.merge_with(&request).to_string();
+        assert!(shown.contains(&format!("{0} (5 bytes)\nhello", dir.join(&name).display())));
+    }
+
+    fn json_body(message: &RequestMessage) -> serde_json::Value {
+        match &message.body {
+            RequestBody::Text(text) => serde_json::from_str(text).unwrap(),
+            _ => panic!("expected a json body"),
+        }
+    }
+
+    #[test]
+    fn request_json_is_merged_into_base_json() {
+        let message = merged(
+            "host = \"https://api.local\"\n[json]\nname = \"base\"\npage = 1\ntags = [\"a\", \"b\"]",
+            "method = \"POST\"\npath = \"/\"\n[json]\npage = 2\ntags = [\"c\"]\nextra = true",
+        );
+        assert_eq!(
+            json_body(&message),
+            serde_json::json!({"name": "base", "page": 2, "tags": ["c"], "extra": true})
+        );
+        assert!(message
+            .headers
+            .iter()
+            .any(|(k, v)| k == "Content-Type" && v == "application/json"));
+    }
+
+    #[test]
+    fn request_json_overrides_nested_keys_only() {
+        let message = merged(
+            "host = \"https://api.local\"\n[json.filter]\nstatus = \"open\"\nowner = \"me\"\n[json.filter.range]\nfrom = 1\nto = 10",
+            "method = \"POST\"\npath = \"/\"\n[json.filter.range]\nto = 20",
+        );
+        assert_eq!(
+            json_body(&message),
+            serde_json::json!({"filter": {"status": "open", "owner": "me", "range": {"from": 1, "to": 20}}})
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_unset_field() {
+        let error = RequestMessage::from_text("unset = [\"hedaers\"]").unwrap_err();
+        assert_eq!(error.to_string(), "invalid unset: unknown field hedaers");
+    }
+
+    fn table(text: &str) -> toml::Table {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn joins_host_and_path_with_one_slash() {
+        for (host, path) in [
+            ("https://api.local", "users"),
+            ("https://api.local/", "/users"),
+            ("https://api.local", "/users"),
+        ] {
+            let url = build_url(host, path, None).unwrap();
+            assert_eq!(url.as_str(), "https://api.local/users");
+        }
+        let url = build_url("https://api.local/v1/", "/users", None).unwrap();
+        assert_eq!(url.as_str(), "https://api.local/v1/users");
+    }
+
+    #[test]
+    fn keeps_query_parameters_in_file_order() {
+        let query = table("z = \"last\"\ntag = [\"a b\", \"c&d\"]\npage = 2");
+        let url = build_url("https://api.local?v=1", "/search?q=x", Some(&query)).unwrap();
+        assert_eq!(
+            url.as_str(),
+            "https://api.local/search?v=1&q=x&z=last&tag=a+b&tag=c%26d&page=2"
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_urls() {
+        assert!(build_url("ftp://files.local", "/", None).is_err());
+        assert!(build_url("api.local", "/", None).is_err());
+        let query = table("filter = { name = \"x\" }");
+        assert!(build_url("https://api.local", "/", Some(&query)).is_err());
+    }
+
+    fn method(method: &str, custom_method: bool) -> Result<HttpVerb, RequestError> {
+        RequestMessage::from_text(&format!(
+            "method = {method:?}\ncustom_method = {custom_method}\nhost = \"https://api.local\"\npath = \"/\""
+        ))?
+        .to_message()
+        .map(|message| message.method)
+    }
+
+    #[test]
+    fn rejects_misspelled_method() {
+        let error = method("POTS", false).err().unwrap();
+        assert!(matches!(
+            &error,
+            RequestError::InvalidValue { property_name, message }
+                if property_name == "method" && message.contains("POTS")
+        ));
+    }
+
+    #[test]
+    fn accepts_registered_extension_methods() {
+        for name in ["PURGE", "PROPFIND", "MKCOL", "QUERY", "TRACE", "CONNECT"] {
+            assert!(
+                matches!(method(name, false), Ok(HttpVerb::Custom(sent)) if sent == name),
+                "{name} was not accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn custom_method_must_be_a_token() {
+        assert!(matches!(
+            method("MY METHOD", true),
+            Err(RequestError::InvalidValue { .. })
+        ));
+        assert!(matches!(
+            method("", true),
+            Err(RequestError::InvalidValue { .. })
+        ));
+        assert!(matches!(method("X-Sync", true), Ok(HttpVerb::Custom(sent)) if sent == "X-Sync"));
+    }
+
+    // known methods are case insensitive, custom ones are sent as written
+    #[test]
+    fn reads_lowercase_methods() {
+        assert!(matches!(method("post", false), Ok(HttpVerb::POST)));
+        assert!(
+            matches!(method("propfind", false), Ok(HttpVerb::Custom(sent)) if sent == "PROPFIND")
+        );
+        assert!(method("sync", false).is_err());
+        assert!(matches!(method("sync", true), Ok(HttpVerb::Custom(sent)) if sent == "sync"));
     }
 }
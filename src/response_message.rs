@@ -1,17 +1,73 @@
 use std::fmt::Display;
 
+use crate::assertion::AssertionResult;
+use crate::body_stream::BodyStream;
+use crate::certificate::{ConnectionInfo, Peer};
+use crate::compression::CompressedBody;
+use crate::event_stream::EventStream;
+use crate::grpc::GrpcStream;
+use crate::request_error::RequestError;
+
 pub struct ResponseMessage {
+    pub method: String,
+    pub url: String,
     pub status: u16,
+    pub version: String,
+    pub compressed_request: Option<CompressedBody>,
     pub time_in_ms: u128,
     pub body: String,
     pub(crate) headers: Vec<String>,
+    pub redirects: Vec<ResponseMessage>,
+    pub connection: Option<Result<ConnectionInfo, RequestError>>,
+    pub peer: Option<Peer>,
+    pub assertions: Vec<AssertionResult>,
+    pub event_stream: Option<EventStream>,
+    pub body_stream: Option<BodyStream>,
+    pub grpc_stream: Option<GrpcStream>,
+}
+
+impl ResponseMessage {
+    pub fn location(&self) -> Option<&str> {
+        self.header("location")
+    }
+
+    pub fn content_length(&self) -> Option<u64> {
+        self.header("content-length")
+            .and_then(|length| length.parse().ok())
+    }
+
+    // every redirect followed, then the final response
+    pub fn hops(&self) -> Vec<&ResponseMessage> {
+        self.redirects.iter().chain([self]).collect()
+    }
+
+    fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find_map(|header| {
+            header
+                .split_once(':')
+                .filter(|(header_name, _)| header_name.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.trim())
+        })
+    }
 }
 
 impl Display for ResponseMessage {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "random")?;
+        writeln!(f, "{0} {1}", self.method, self.url)?;
         writeln!(f, "STATUS CODE: {}", self.status)?;
+        writeln!(f, "protocol: {}", self.version)?;
+        if let Some(compressed_request) = &self.compressed_request {
+            writeln!(f, "request body: {compressed_request}")?;
+        }
         writeln!(f, "took: {}ms\n ", self.time_in_ms)?;
+        if !self.assertions.is_empty() {
+            writeln!(f, "assertions")?;
+            for assertion in self.assertions.iter() {
+                writeln!(f, "{assertion}")?;
+            }
+            writeln!(f)?;
+        }
         for h in self.headers.iter() {
             writeln!(f, "{h}")?;
         }
@@ -0,0 +1,109 @@
+use std::{fmt::Display, time::Duration};
+
+use serde::Deserialize;
+
+use crate::request_message::HttpVerb;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RetryConfig {
+    count: u32,
+    #[serde(default = "default_backoff_ms")]
+    backoff_ms: u64,
+    #[serde(default = "default_statuses")]
+    statuses: Vec<u16>,
+    #[serde(default)]
+    non_idempotent: bool,
+}
+
+fn default_backoff_ms() -> u64 {
+    500
+}
+
+fn default_statuses() -> Vec<u16> {
+    vec![429, 502, 503, 504]
+}
+
+impl RetryConfig {
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    pub fn retries_status(&self, status: u16) -> bool {
+        self.statuses.contains(&status)
+    }
+
+    // a timed out POST may have been handled already, so only methods that
+    // are safe to repeat are sent again unless the request says otherwise
+    pub fn retries_transport_error(&self, method: &HttpVerb) -> bool {
+        self.non_idempotent || method.is_idempotent()
+    }
+
+    // the wait doubles after every failed attempt
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        Duration::from_millis(self.backoff_ms.saturating_mul(1 << attempt.min(16)))
+    }
+}
+
+impl Display for RetryConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let statuses = self
+            .statuses
+            .iter()
+            .map(|s| s.to_string())
+            .collect::<Vec<String>>()
+            .join(", ");
+        let methods = match self.non_idempotent {
+            true => "any method",
+            false => "idempotent methods",
+        };
+        write!(
+            f,
+            "{0} retries, backoff {1} ms, on {statuses} and on timeouts and refused connections of {methods}",
+            self.count, self.backoff_ms
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(table: &str) -> RetryConfig {
+        toml::from_str(table).unwrap()
+    }
+
+    #[test]
+    fn doubles_the_backoff_after_every_attempt() {
+        let retry = config("count = 3\nbackoff_ms = 100");
+        let waits = (0..4).map(|attempt| retry.backoff(attempt).as_millis());
+        assert_eq!(waits.collect::<Vec<u128>>(), [100, 200, 400, 800]);
+        assert_eq!(config("count = 1").backoff(0), Duration::from_millis(500));
+        // the doubling stops growing instead of overflowing
+        assert_eq!(retry.backoff(40), retry.backoff(16));
+    }
+
+    #[test]
+    fn retries_listed_statuses() {
+        let retry = config("count = 1");
+        assert!([429, 502, 503, 504]
+            .iter()
+            .all(|s| retry.retries_status(*s)));
+        assert!(!retry.retries_status(500));
+        let retry = config("count = 1\nstatuses = [500]");
+        assert!(retry.retries_status(500) && !retry.retries_status(503));
+    }
+
+    #[test]
+    fn retries_transport_errors_of_idempotent_methods_only() {
+        let retry = config("count = 1");
+        assert!(retry.retries_transport_error(&HttpVerb::GET));
+        assert!(retry.retries_transport_error(&HttpVerb::PUT));
+        assert!(retry.retries_transport_error(&HttpVerb::Custom("PROPFIND".to_owned())));
+        assert!(!retry.retries_transport_error(&HttpVerb::POST));
+        assert!(!retry.retries_transport_error(&HttpVerb::PATCH));
+        assert!(!retry.retries_transport_error(&HttpVerb::Custom("LOCK".to_owned())));
+
+        let retry = config("count = 1\nnon_idempotent = true");
+        assert!(retry.retries_transport_error(&HttpVerb::POST));
+    }
+}
@@ -0,0 +1,153 @@
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+use url::Url;
+
+use crate::cookie_jar::{Cookie, CookieJar};
+
+#[derive(Debug, Clone)]
+pub struct Token {
+    pub access_token: String,
+    pub expires_at: Option<Instant>,
+    pub refresh_token: Option<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Session {
+    tokens: Arc<Mutex<HashMap<String, Token>>>,
+    cookies: Arc<Mutex<CookieStore>>,
+}
+
+#[derive(Debug, Default)]
+struct CookieStore {
+    environment: String,
+    jars: HashMap<String, CookieJar>,
+    persist: bool,
+}
+
+impl Session {
+    pub fn load() -> Session {
+        let jars = cookies_path()
+            .and_then(|path| fs::read_to_string(path).ok())
+            .and_then(|content| toml::from_str::<HashMap<String, CookieJar>>(&content).ok());
+        let session = Session::default();
+        if let (Some(jars), Ok(mut store)) = (jars, session.cookies.lock()) {
+            store.jars = jars;
+            store.persist = true;
+        }
+        session
+    }
+
+    pub fn token(&self, key: &str) -> Option<Token> {
+        self.tokens.lock().ok()?.get(key).cloned()
+    }
+
+    pub fn store_token(&self, key: &str, token: Token) {
+        if let Ok(mut tokens) = self.tokens.lock() {
+            tokens.insert(key.to_owned(), token);
+        }
+    }
+
+    pub fn take_token(&self, key: &str) -> Option<Token> {
+        self.tokens.lock().ok()?.remove(key)
+    }
+
+    pub fn set_environment(&self, environment: &str) {
+        if let Ok(mut store) = self.cookies.lock() {
+            store.environment = environment.to_owned();
+        }
+    }
+
+    pub fn cookie_header(&self, url: &Url) -> Option<String> {
+        let mut store = self.cookies.lock().ok()?;
+        let environment = store.environment.clone();
+        store.jars.get_mut(&environment)?.header_for(url)
+    }
+
+    pub fn store_cookies<'a>(&self, url: &Url, set_cookies: impl Iterator<Item = &'a str>) {
+        self.update_jar(|jar| set_cookies.for_each(|set_cookie| jar.store(url, set_cookie)));
+    }
+
+    pub fn cookies(&self) -> Vec<Cookie> {
+        self.cookies
+            .lock()
+            .ok()
+            .and_then(|store| {
+                store
+                    .jars
+                    .get(&store.environment)
+                    .map(|jar| jar.cookies().to_vec())
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn set_cookie_value(&self, index: usize, value: String) {
+        self.update_jar(|jar| jar.set_value(index, value));
+    }
+
+    pub fn remove_cookie(&self, index: usize) {
+        self.update_jar(|jar| jar.remove(index));
+    }
+
+    pub fn persist_cookies(&self) -> bool {
+        self.cookies.lock().is_ok_and(|store| store.persist)
+    }
+
+    pub fn set_persist_cookies(&self, persist: bool) {
+        if let Ok(mut store) = self.cookies.lock() {
+            store.persist = persist;
+            if persist {
+                save_cookies(&store.jars);
+            } else if let Some(path) = cookies_path().filter(|p| p.exists()) {
+                if let Err(e) = fs::remove_file(&path) {
+                    println!("could not remove {0}: {e}", path.display());
+                }
+            }
+        }
+    }
+
+    fn update_jar(&self, update: impl FnOnce(&mut CookieJar)) {
+        if let Ok(mut store) = self.cookies.lock() {
+            let environment = store.environment.clone();
+            update(store.jars.entry(environment).or_default());
+            if store.persist {
+                save_cookies(&store.jars);
+            }
+        }
+    }
+}
+
+pub fn config_path(file_name: &str) -> Option<PathBuf> {
+    let config_dir = env::var("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or(env::var("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .ok()?;
+    Some(config_dir.join("ghucl").join(file_name))
+}
+
+fn cookies_path() -> Option<PathBuf> {
+    config_path("cookies.toml")
+}
+
+fn save_cookies(jars: &HashMap<String, CookieJar>) {
+    let Some(path) = cookies_path() else {
+        println!("could not find a config folder to save the cookies");
+        return;
+    };
+    let result = toml::to_string(jars)
+        .map_err(|e| e.to_string())
+        .and_then(|content| {
+            path.parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::write(&path, content))
+                .map_err(|e| e.to_string())
+        });
+    if let Err(e) = result {
+        println!("could not save cookies in {0}: {e}", path.display());
+    }
+}
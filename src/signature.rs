@@ -0,0 +1,321 @@
+use std::fmt::Display;
+
+use async_std::io::ReadExt;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::Deserialize;
+use sha1::Sha1;
+use sha2::{Sha256, Sha512};
+use url::Url;
+
+use crate::aws_sigv4;
+use crate::request_body::RequestBody;
+use crate::request_error::RequestError;
+use crate::request_message::RequestMessage;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct SignatureConfig {
+    secret: String,
+    header: String,
+    template: String,
+    #[serde(default)]
+    algorithm: Algorithm,
+    #[serde(default)]
+    encoding: Encoding,
+    #[serde(default)]
+    prefix: String,
+    #[serde(default)]
+    timestamp_format: TimestampFormat,
+    timestamp_header: Option<String>,
+    nonce_header: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Algorithm {
+    Sha1,
+    #[default]
+    Sha256,
+    Sha512,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Encoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TimestampFormat {
+    #[default]
+    Unix,
+    UnixMs,
+    Rfc3339,
+}
+
+impl SignatureConfig {
+    pub async fn sign(&self, request_message: &mut RequestMessage) -> Result<(), RequestError> {
+        let now = Utc::now();
+        let timestamp = match self.timestamp_format {
+            TimestampFormat::Unix => now.timestamp().to_string(),
+            TimestampFormat::UnixMs => now.timestamp_millis().to_string(),
+            TimestampFormat::Rfc3339 => now.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        };
+        let mut nonce_bytes = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut nonce_bytes);
+        let nonce = hex::encode(nonce_bytes);
+
+        if let Some(timestamp_header) = &self.timestamp_header {
+            request_message.set_header(timestamp_header, &timestamp);
+        }
+        if let Some(nonce_header) = &self.nonce_header {
+            request_message.set_header(nonce_header, &nonce);
+        }
+
+        // a file body is read only for the placeholders that need it
+        let body = match self.template.contains("{body}") {
+            true => body_bytes(&request_message.body).await?,
+            false => Vec::new(),
+        };
+        let body_sha256 = match self.template.contains("{body_sha256}") {
+            true => aws_sigv4::payload_hash(&request_message.body).await?,
+            false => String::new(),
+        };
+        let canonical = self.render(request_message, &body, &body_sha256, &timestamp, &nonce)?;
+        request_message.set_header(&self.header, &self.signature(&canonical));
+        Ok(())
+    }
+
+    // the headers the signature sets, dropped when a redirect leaves the origin
+    pub fn headers(&self) -> impl Iterator<Item = &str> {
+        [
+            Some(self.header.as_str()),
+            self.timestamp_header.as_deref(),
+            self.nonce_header.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+    }
+
+    fn signature(&self, canonical: &[u8]) -> String {
+        let digest = match self.algorithm {
+            Algorithm::Sha1 => hmac::<Hmac<Sha1>>(&self.secret, canonical),
+            Algorithm::Sha256 => hmac::<Hmac<Sha256>>(&self.secret, canonical),
+            Algorithm::Sha512 => hmac::<Hmac<Sha512>>(&self.secret, canonical),
+        };
+        let encoded = match self.encoding {
+            Encoding::Hex => hex::encode(digest),
+            Encoding::Base64 => STANDARD.encode(digest),
+        };
+        format!("{0}{encoded}", self.prefix)
+    }
+
+    fn render(
+        &self,
+        request_message: &RequestMessage,
+        body: &[u8],
+        body_sha256: &str,
+        timestamp: &str,
+        nonce: &str,
+    ) -> Result<Vec<u8>, RequestError> {
+        let invalid = |message: String| RequestError::InvalidValue {
+            property_name: "signature.template".to_owned(),
+            message,
+        };
+        let url =
+            Url::parse(&request_message.wire_url()).map_err(|e| RequestError::InvalidUrl {
+                url: request_message.url.clone(),
+                message: e.to_string(),
+            })?;
+
+        let mut rendered = Vec::new();
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find(['{', '}']) {
+            rendered.extend_from_slice(&rest.as_bytes()[..start]);
+            let tail = &rest[start..];
+            if tail.starts_with("{{") || tail.starts_with("}}") {
+                rendered.push(tail.as_bytes()[0]);
+                rest = &tail[2..];
+                continue;
+            }
+            let end = match (tail.starts_with('{'), tail.find('}')) {
+                (true, Some(end)) => end,
+                _ => return Err(invalid(format!("unbalanced braces in {0}", self.template))),
+            };
+
+            let placeholder = &tail[1..end];
+            match placeholder {
+                "method" => {
+                    rendered.extend_from_slice(request_message.method.to_string().as_bytes())
+                }
+                "url" => rendered.extend_from_slice(url.as_str().as_bytes()),
+                "host" => rendered.extend_from_slice(url.host_str().unwrap_or_default().as_bytes()),
+                "path" => rendered.extend_from_slice(url.path().as_bytes()),
+                "query" => rendered.extend_from_slice(url.query().unwrap_or_default().as_bytes()),
+                "body" => rendered.extend_from_slice(body),
+                "body_sha256" => rendered.extend_from_slice(body_sha256.as_bytes()),
+                "timestamp" => rendered.extend_from_slice(timestamp.as_bytes()),
+                "nonce" => rendered.extend_from_slice(nonce.as_bytes()),
+                other => match other.strip_prefix("header:") {
+                    Some(name) => {
+                        let value = request_message
+                            .headers
+                            .iter()
+                            .find(|(k, _)| k.eq_ignore_ascii_case(name))
+                            .map(|(_, v)| v.as_str())
+                            .unwrap_or_default();
+                        rendered.extend_from_slice(value.as_bytes());
+                    }
+                    None => return Err(invalid(format!("unknown placeholder {{{other}}}"))),
+                },
+            }
+            rest = &tail[end + 1..];
+        }
+        rendered.extend_from_slice(rest.as_bytes());
+
+        Ok(rendered)
+    }
+}
+
+fn hmac<M: Mac + hmac::digest::KeyInit>(secret: &str, data: &[u8]) -> Vec<u8> {
+    let mut mac =
+        <M as Mac>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+async fn body_bytes(body: &RequestBody) -> Result<Vec<u8>, RequestError> {
+    match body {
+        RequestBody::Text(text) => Ok(text.as_bytes().to_vec()),
+        RequestBody::Bytes(bytes) => Ok(bytes.clone()),
+        RequestBody::File(path) => {
+            async_std::fs::read(path)
+                .await
+                .map_err(|e| RequestError::InvalidValue {
+                    property_name: "body_file".to_owned(),
+                    message: format!("could not read {0}: {e}", path.display()),
+                })
+        }
+        RequestBody::CompressedFile(file) => {
+            let mut bytes = Vec::new();
+            let read = match file.open().await {
+                Ok(mut reader) => reader.read_to_end(&mut bytes).await,
+                Err(e) => Err(e),
+            };
+            read.map(|_| bytes).map_err(|e| RequestError::InvalidValue {
+                property_name: "body_file".to_owned(),
+                message: format!("could not read {0}: {e}", file.path().display()),
+            })
+        }
+    }
+}
+
+impl Display for SignatureConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let algorithm = match self.algorithm {
+            Algorithm::Sha1 => "sha1",
+            Algorithm::Sha256 => "sha256",
+            Algorithm::Sha512 => "sha512",
+        };
+        write!(f, "hmac-{algorithm} in {0}, secret ***", self.header)?;
+        write!(f, "\ntemplate {0:?}", self.template)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(table: &str) -> SignatureConfig {
+        toml::from_str(table).unwrap()
+    }
+
+    fn message(text: &str) -> RequestMessage {
+        RequestMessage::from_text(text)
+            .unwrap()
+            .to_message()
+            .unwrap()
+    }
+
+    fn signed(config: &SignatureConfig, text: &str) -> String {
+        let mut message = message(text);
+        async_std::task::block_on(config.sign(&mut message)).unwrap();
+        message.headers["X-Signature"].clone()
+    }
+
+    const REQUEST: &str = "method = \"POST\"\nhost = \"https://api.local\"\npath = \"/orders\"\nbody = \"abc\"\n[query]\nb = \"2\"\na = \"x y\"\n[headers]\nX-Client = \"shop\"";
+
+    fn rendered(template: &str) -> Result<String, RequestError> {
+        let config = config(&format!(
+            "secret = \"s\"\nheader = \"X-Signature\"\ntemplate = {template:?}"
+        ));
+        config
+            .render(&message(REQUEST), b"abc", "sha", "1700000000", "n0")
+            .map(|bytes| String::from_utf8(bytes).unwrap())
+    }
+
+    #[test]
+    fn renders_request_placeholders() {
+        assert_eq!(
+            rendered("{method} {host}{path}?{query} {header:x-client} {header:missing}|").unwrap(),
+            "POST api.local/orders?b=2&a=x+y shop |"
+        );
+        assert_eq!(
+            rendered("{body}:{body_sha256}:{timestamp}:{nonce}:{{literal}}").unwrap(),
+            "abc:sha:1700000000:n0:{literal}"
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_placeholders_and_braces() {
+        assert_eq!(
+            rendered("{method}\n{verb}").unwrap_err().to_string(),
+            "invalid signature.template: unknown placeholder {verb}"
+        );
+        assert!(rendered("{method").is_err());
+    }
+
+    // rfc 4231, test case 2
+    #[test]
+    fn signs_with_hmac_sha256() {
+        let config = config(
+            "secret = \"Jefe\"\nheader = \"X-Signature\"\ntemplate = \"what do ya want for nothing?\"\nprefix = \"v1=\"",
+        );
+        assert_eq!(
+            signed(&config, REQUEST),
+            "v1=5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn hashes_the_body() {
+        let by_placeholder = config(
+            "secret = \"s\"\nheader = \"X-Signature\"\ntemplate = \"{body_sha256}\"\nencoding = \"base64\"",
+        );
+        let by_value = config(
+            "secret = \"s\"\nheader = \"X-Signature\"\nencoding = \"base64\"\ntemplate = \"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad\"",
+        );
+        assert_eq!(signed(&by_placeholder, REQUEST), signed(&by_value, REQUEST));
+    }
+
+    #[test]
+    fn uses_a_new_nonce_for_every_signature() {
+        let config = config(
+            "secret = \"s\"\nheader = \"X-Signature\"\ntemplate = \"{nonce}\"\nnonce_header = \"X-Nonce\"",
+        );
+        let mut message = message(REQUEST);
+        async_std::task::block_on(config.sign(&mut message)).unwrap();
+        let first = message.headers["X-Nonce"].clone();
+        async_std::task::block_on(config.sign(&mut message)).unwrap();
+        assert_ne!(message.headers["X-Nonce"], first);
+        assert_eq!(
+            config.headers().collect::<Vec<&str>>(),
+            ["X-Signature", "X-Nonce"]
+        );
+    }
+}
@@ -0,0 +1,179 @@
+use std::{fmt::Display, fs, path::Path};
+
+use async_native_tls::{Certificate, Identity, TlsConnector};
+use isahc::config::{CaCertificate, ClientCertificate, Configurable, PrivateKey, SslOption};
+use isahc::HttpClientBuilder;
+use openssl::{error::ErrorStack, ssl::SslConnectorBuilder, ssl::SslFiletype};
+use serde::Deserialize;
+
+use crate::request_error::RequestError;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TlsConfig {
+    ca_bundle: Option<String>,
+    client_cert: Option<String>,
+    client_key: Option<String>,
+    password: Option<String>,
+    #[serde(default)]
+    insecure: bool,
+}
+
+impl TlsConfig {
+    pub fn insecure(&self) -> bool {
+        self.insecure
+    }
+
+    pub fn relative_to(&mut self, dir: &Path) {
+        for path in [
+            &mut self.ca_bundle,
+            &mut self.client_cert,
+            &mut self.client_key,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            *path = dir.join(&*path).to_string_lossy().to_string();
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), RequestError> {
+        let files = [
+            ("tls.ca_bundle", &self.ca_bundle),
+            ("tls.client_cert", &self.client_cert),
+            ("tls.client_key", &self.client_key),
+        ];
+        for (property_name, path) in files {
+            if let Some(path) = path {
+                fs::metadata(path).map_err(|e| RequestError::InvalidValue {
+                    property_name: property_name.to_owned(),
+                    message: format!("could not read {path}: {e}"),
+                })?;
+            }
+        }
+        if self.client_key.is_some() && self.client_cert.is_none() {
+            return Err(RequestError::InvalidValue {
+                property_name: "tls.client_key".to_owned(),
+                message: "a client key needs a client_cert".to_owned(),
+            });
+        }
+        Ok(())
+    }
+
+    pub fn configure(&self, mut builder: HttpClientBuilder) -> HttpClientBuilder {
+        if let Some(ca_bundle) = &self.ca_bundle {
+            builder = builder.ssl_ca_certificate(CaCertificate::file(ca_bundle));
+        }
+        if let Some(client_cert) = &self.client_cert {
+            let extension = Path::new(client_cert)
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            let certificate = match extension.as_str() {
+                "p12" | "pfx" => ClientCertificate::pkcs12_file(client_cert, self.password.clone()),
+                "der" => ClientCertificate::der_file(client_cert, self.private_key()),
+                _ => ClientCertificate::pem_file(client_cert, self.private_key()),
+            };
+            builder = builder.ssl_client_certificate(certificate);
+        }
+        if self.insecure {
+            builder = builder.ssl_options(
+                SslOption::DANGER_ACCEPT_INVALID_CERTS | SslOption::DANGER_ACCEPT_INVALID_HOSTS,
+            );
+        }
+        builder
+    }
+
+    // pem client certificates are also offered when the connection is inspected,
+    // for servers that end the handshake without one
+    pub fn configure_inspection(
+        &self,
+        builder: &mut SslConnectorBuilder,
+    ) -> Result<(), ErrorStack> {
+        let Some(client_cert) = &self.client_cert else {
+            return Ok(());
+        };
+        let is_pem = |path: &str| {
+            let path = path.to_lowercase();
+            !(path.ends_with(".p12") || path.ends_with(".pfx") || path.ends_with(".der"))
+        };
+        if is_pem(client_cert) && self.password.is_none() {
+            builder.set_certificate_chain_file(client_cert)?;
+            let client_key = self.client_key.as_ref().unwrap_or(client_cert);
+            if is_pem(client_key) {
+                builder.set_private_key_file(client_key, SslFiletype::PEM)?;
+            }
+        }
+        Ok(())
+    }
+
+    // websockets do not go through curl, the same settings are given to native-tls
+    pub fn websocket_connector(&self) -> Result<TlsConnector, String> {
+        let read = |path: &str| fs::read(path).map_err(|e| format!("could not read {path}: {e}"));
+        let mut connector = TlsConnector::new();
+        if let Some(ca_bundle) = &self.ca_bundle {
+            let bundle = read(ca_bundle)?;
+            let certificates = match ca_bundle.to_lowercase().ends_with(".der") {
+                true => Certificate::from_der(&bundle).map(|c| vec![c]),
+                false => Certificate::stack_from_pem(&bundle),
+            }
+            .map_err(|e| format!("{ca_bundle}: {e}"))?;
+            for certificate in certificates {
+                connector = connector.add_root_certificate(certificate);
+            }
+        }
+        if let Some(client_cert) = &self.client_cert {
+            let lower = client_cert.to_lowercase();
+            let identity = if lower.ends_with(".p12") || lower.ends_with(".pfx") {
+                Identity::from_pkcs12(
+                    &read(client_cert)?,
+                    self.password.as_deref().unwrap_or_default(),
+                )
+            } else if lower.ends_with(".der") || self.password.is_some() {
+                return Err(format!(
+                    "{client_cert}: websockets need a p12 file or an unencrypted pem certificate"
+                ));
+            } else {
+                let key = read(self.client_key.as_ref().unwrap_or(client_cert))?;
+                Identity::from_pkcs8(&read(client_cert)?, &key)
+            }
+            .map_err(|e| format!("{client_cert}: {e}"))?;
+            connector = connector.identity(identity);
+        }
+        if self.insecure {
+            connector = connector
+                .danger_accept_invalid_certs(true)
+                .danger_accept_invalid_hostnames(true);
+        }
+        Ok(connector)
+    }
+
+    fn private_key(&self) -> Option<PrivateKey> {
+        let client_key = self.client_key.as_ref()?;
+        match client_key.to_lowercase().ends_with(".der") {
+            true => Some(PrivateKey::der_file(client_key, self.password.clone())),
+            false => Some(PrivateKey::pem_file(client_key, self.password.clone())),
+        }
+    }
+}
+
+impl Display for TlsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(ca_bundle) = &self.ca_bundle {
+            writeln!(f, "ca bundle {ca_bundle}")?;
+        }
+        if let Some(client_cert) = &self.client_cert {
+            write!(f, "client certificate {client_cert}")?;
+            if let Some(client_key) = &self.client_key {
+                write!(f, ", key {client_key}")?;
+            }
+            if self.password.is_some() {
+                write!(f, ", password ***")?;
+            }
+            writeln!(f)?;
+        }
+        if self.insecure {
+            writeln!(f, "certificate verification disabled")?;
+        }
+        Ok(())
+    }
+}
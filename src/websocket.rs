@@ -0,0 +1,346 @@
+use std::{
+    fmt::Display,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use async_std::{future::timeout, net::TcpStream, task};
+use async_tungstenite::async_std::{client_async_tls_with_connector, ClientStream};
+use async_tungstenite::tungstenite::{
+    client::IntoClientRequest,
+    http::{HeaderName, HeaderValue},
+    Message as Frame,
+};
+use async_tungstenite::WebSocketStream;
+use chrono::{DateTime, Local};
+use iced::futures::{
+    channel::mpsc::{self, Sender, UnboundedReceiver, UnboundedSender},
+    future, select, SinkExt, StreamExt,
+};
+use iced::subscription::{self, Subscription};
+use url::Url;
+
+use crate::auth::Auth;
+use crate::http_client;
+use crate::proxy;
+use crate::request_error::RequestError;
+use crate::request_message::RequestMessage;
+use crate::session::Session;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const MAX_MESSAGES: usize = 1000;
+// binary messages are shown as hex up to this many bytes
+const BINARY_PREVIEW: usize = 64;
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+type Socket = WebSocketStream<ClientStream<TcpStream>>;
+// the request and the queue of messages to send, taken once the connection starts
+type Connection = Arc<Mutex<Option<(RequestMessage, UnboundedReceiver<Outgoing>)>>>;
+
+#[derive(Debug, Clone)]
+pub enum Payload {
+    Text(String),
+    Binary(Vec<u8>),
+}
+
+#[derive(Debug, Clone)]
+pub struct WebSocketMessage {
+    pub sent: bool,
+    pub at: DateTime<Local>,
+    pub payload: Payload,
+}
+
+#[derive(Debug, Clone)]
+pub enum WebSocketUpdate {
+    Connected(String),
+    Message(WebSocketMessage),
+    Closed(String),
+}
+
+enum Outgoing {
+    Message(Payload),
+    Close,
+}
+
+pub struct WebSocketSession {
+    pub messages: Vec<WebSocketMessage>,
+    pub status: String,
+    pub running: bool,
+    pub draft: String,
+    pub binary: bool,
+    id: u64,
+    outgoing: UnboundedSender<Outgoing>,
+    connection: Connection,
+}
+
+impl WebSocketSession {
+    pub fn new(request: RequestMessage) -> Self {
+        let (outgoing, receiver) = mpsc::unbounded();
+        // the first messages wait in the queue until the connection is open
+        for message in request.messages.iter() {
+            let _ = outgoing.unbounded_send(Outgoing::Message(Payload::Text(message.clone())));
+        }
+        WebSocketSession {
+            messages: Vec::new(),
+            status: format!("connecting to {0}", websocket_url(&request.url)),
+            running: true,
+            draft: String::new(),
+            binary: false,
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            outgoing,
+            connection: Arc::new(Mutex::new(Some((request, receiver)))),
+        }
+    }
+
+    pub fn subscription(&self, session: &Session) -> Subscription<WebSocketUpdate> {
+        if !self.running {
+            return Subscription::none();
+        }
+        let connection = self.connection.clone();
+        let session = session.clone();
+        subscription::channel(self.id, 100, move |mut output| async move {
+            let connection = connection.lock().ok().and_then(|mut c| c.take());
+            let reason = match connection {
+                Some((request, outgoing)) => run(request, outgoing, &session, &mut output).await,
+                None => "closed".to_owned(),
+            };
+            let _ = output.send(WebSocketUpdate::Closed(reason)).await;
+            future::pending().await
+        })
+    }
+
+    pub fn update(&mut self, update: WebSocketUpdate) {
+        match update {
+            WebSocketUpdate::Connected(status) => self.status = status,
+            WebSocketUpdate::Message(message) => {
+                if self.messages.len() == MAX_MESSAGES {
+                    self.messages.remove(0);
+                }
+                self.messages.push(message);
+            }
+            WebSocketUpdate::Closed(reason) => {
+                self.running = false;
+                self.status = reason;
+            }
+        }
+    }
+
+    pub fn send_draft(&mut self) {
+        let payload = match self.binary {
+            true => {
+                let hex: String = self.draft.split_whitespace().collect();
+                match hex::decode(hex) {
+                    Ok(bytes) => Payload::Binary(bytes),
+                    Err(e) => {
+                        self.status = format!("not sent, the message is not valid hex: {e}");
+                        return;
+                    }
+                }
+            }
+            false => Payload::Text(self.draft.clone()),
+        };
+        if self
+            .outgoing
+            .unbounded_send(Outgoing::Message(payload))
+            .is_ok()
+        {
+            self.draft.clear();
+        }
+    }
+
+    pub fn close(&mut self) {
+        self.status = "closing".to_owned();
+        let _ = self.outgoing.unbounded_send(Outgoing::Close);
+    }
+}
+
+fn websocket_url(url: &str) -> String {
+    match url.split_once("://") {
+        Some(("https", rest)) => format!("wss://{rest}"),
+        Some((_, rest)) => format!("ws://{rest}"),
+        None => url.to_owned(),
+    }
+}
+
+// returns why the connection ended
+async fn run(
+    request: RequestMessage,
+    outgoing: UnboundedReceiver<Outgoing>,
+    session: &Session,
+    output: &mut Sender<WebSocketUpdate>,
+) -> String {
+    let limit = request.timeout.unwrap_or(CONNECT_TIMEOUT);
+    let socket = match timeout(limit, connect(request, session)).await {
+        Ok(Ok((socket, status))) => {
+            let _ = output.send(WebSocketUpdate::Connected(status)).await;
+            socket
+        }
+        Ok(Err(e)) => return format!("could not connect: {e}"),
+        Err(_) => return format!("could not connect within {0}ms", limit.as_millis()),
+    };
+
+    let (mut write, read) = socket.split();
+    let mut read = read.fuse();
+    let mut outgoing = outgoing.fuse();
+    let received = |payload| {
+        WebSocketUpdate::Message(WebSocketMessage {
+            sent: false,
+            at: Local::now(),
+            payload,
+        })
+    };
+    loop {
+        select! {
+            frame = read.next() => match frame {
+                Some(Ok(Frame::Text(text))) => {
+                    let _ = output.send(received(Payload::Text(text.to_string()))).await;
+                }
+                Some(Ok(Frame::Binary(bytes))) => {
+                    let _ = output.send(received(Payload::Binary(bytes.to_vec()))).await;
+                }
+                Some(Ok(Frame::Close(Some(frame)))) => {
+                    return format!(
+                        "closed by the server, code {0} {1}",
+                        u16::from(frame.code),
+                        frame.reason
+                    )
+                }
+                Some(Ok(Frame::Close(None))) | None => {
+                    return "closed by the server".to_owned()
+                }
+                // pings are answered by tungstenite
+                Some(Ok(_)) => {}
+                Some(Err(e)) => return format!("connection lost: {e}"),
+            },
+            message = outgoing.next() => match message {
+                Some(Outgoing::Message(payload)) => {
+                    let frame = match &payload {
+                        Payload::Text(text) => Frame::text(text.as_str()),
+                        Payload::Binary(bytes) => Frame::binary(bytes.clone()),
+                    };
+                    if let Err(e) = write.send(frame).await {
+                        return format!("could not send: {e}");
+                    }
+                    let sent = WebSocketMessage {
+                        sent: true,
+                        at: Local::now(),
+                        payload,
+                    };
+                    let _ = output.send(WebSocketUpdate::Message(sent)).await;
+                }
+                Some(Outgoing::Close) | None => {
+                    let _ = write.send(Frame::Close(None)).await;
+                    return "closed".to_owned();
+                }
+            },
+        }
+    }
+}
+
+async fn connect(
+    mut request: RequestMessage,
+    session: &Session,
+) -> Result<(Socket, String), String> {
+    if request.socket_path.is_some() {
+        return Err("websockets can not be opened over a unix socket".to_owned());
+    }
+    let explicit_cookie = request
+        .headers
+        .iter()
+        .find(|(k, _)| k.eq_ignore_ascii_case("Cookie"))
+        .map(|(_, v)| v.clone());
+    http_client::set_cookie_header(&mut request, explicit_cookie.as_deref(), session)
+        .map_err(|e| e.to_string())?;
+    let auth = request.auth.take();
+    if let Some(Auth::Digest(_)) = auth {
+        // the handshake can not be repeated with the answer to a challenge
+        return Err(RequestError::InvalidValue {
+            property_name: "auth".to_owned(),
+            message: "digest auth is not supported for websockets".to_owned(),
+        }
+        .to_string());
+    }
+    if let Some(auth) = &auth {
+        auth.apply(&mut request, session)
+            .await
+            .map_err(|e| e.to_string())?;
+    }
+    http_client::sign(&mut request, auth.as_ref())
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let url = Url::parse(&request.wire_url()).map_err(|e| e.to_string())?;
+    let host = url.host_str().unwrap_or_default().to_owned();
+    let port = url.port_or_known_default().unwrap_or(80);
+    let limit = request.timeout.unwrap_or(CONNECT_TIMEOUT);
+    let stream = match proxy::resolve(request.proxy.as_ref(), &url) {
+        Some(proxy) => task::spawn_blocking(move || proxy.connect(&host, port, limit))
+            .await
+            .map(TcpStream::from),
+        None => TcpStream::connect((host.as_str(), port)).await,
+    }
+    .map_err(|e| e.to_string())?;
+
+    let mut handshake = websocket_url(url.as_str())
+        .into_client_request()
+        .map_err(|e| e.to_string())?;
+    for (name, value) in request.headers.iter() {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| e.to_string())?;
+        let value = HeaderValue::from_str(value).map_err(|e| e.to_string())?;
+        handshake.headers_mut().insert(name, value);
+    }
+    let connector = match &request.tls {
+        Some(tls) => Some(tls.websocket_connector()?),
+        None => None,
+    };
+    let (socket, response) = client_async_tls_with_connector(handshake, stream, connector)
+        .await
+        .map_err(|e| e.to_string())?;
+    session.store_cookies(
+        &url,
+        response
+            .headers()
+            .get_all("Set-Cookie")
+            .iter()
+            .filter_map(|value| value.to_str().ok()),
+    );
+
+    let mut status = format!(
+        "connected to {0}, {1}",
+        websocket_url(&request.url),
+        response.status()
+    );
+    if let Some(protocol) = response.headers().get("Sec-WebSocket-Protocol") {
+        status.push_str(&format!(
+            ", protocol {0}",
+            String::from_utf8_lossy(protocol.as_bytes())
+        ));
+    }
+    Ok((socket, status))
+}
+
+impl Display for WebSocketMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let direction = if self.sent { "sent" } else { "received" };
+        write!(f, "{0} {direction}", self.at.format("%H:%M:%S%.3f"))?;
+        match &self.payload {
+            Payload::Text(text) => write!(f, "\n{text}"),
+            Payload::Binary(bytes) => {
+                write!(
+                    f,
+                    " {0} bytes\n{1}",
+                    bytes.len(),
+                    hex::encode(&bytes[..bytes.len().min(BINARY_PREVIEW)])
+                )?;
+                if bytes.len() > BINARY_PREVIEW {
+                    write!(f, "...")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}